@@ -0,0 +1,363 @@
+use crate::telemetry;
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{json, Map, Value};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Instrument, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// 访问日志使用的 tracing target，可以通过 `RUST_LOG` 单独控制
+pub const TARGET: &str = "byr_jsdelivr::access_log";
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+static X_CACHE: HeaderName = HeaderName::from_static("x-cache");
+static X_SERVED_VERSION: HeaderName = HeaderName::from_static("x-served-version");
+
+/// 客户端传入的请求 ID 的最大长度
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static CONTEXT: Arc<RequestContext>;
+}
+
+/// 缓存命中情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    /// 命中固定（pin）的包
+    Pinned,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Pinned => "pinned",
+            CacheStatus::Miss => "miss",
+        }
+    }
+
+    fn is_hit(&self) -> bool {
+        *self != CacheStatus::Miss
+    }
+}
+
+/// 处理请求过程中记录的信息，请求结束时写入访问日志
+#[derive(Debug, Default)]
+struct Record {
+    client: Option<IpAddr>,
+    package: Option<String>,
+    version: Option<String>,
+    metadata_cache: Option<CacheStatus>,
+    package_cache: Option<CacheStatus>,
+    compressed_cache: Option<CacheStatus>,
+    upstream_requests: u32,
+    upstream_time: Duration,
+    upstream_bytes: u64,
+}
+
+struct RequestContext {
+    id: String,
+    record: Mutex<Record>,
+}
+
+fn with_record(f: impl FnOnce(&mut Record)) {
+    // 后台任务（预热、变更订阅）不在请求上下文中，直接忽略
+    let _ = CONTEXT.try_with(|context| f(&mut context.record.lock().unwrap()));
+}
+
+/// 当前请求的 ID，转发给上游以便关联日志
+pub fn request_id() -> Option<String> {
+    CONTEXT.try_with(|context| context.id.clone()).ok()
+}
+
+/// 记录经过信任代理解析后的客户端地址
+pub fn record_client(ip: IpAddr) {
+    with_record(|record| record.client = Some(ip));
+}
+
+/// 记录解析出的包名（含来源前缀）与实际返回的版本
+pub fn record_package(name: &str, version: &str) {
+    with_record(|record| {
+        record.package = Some(name.to_string());
+        record.version = Some(version.to_string());
+    });
+}
+
+pub fn record_metadata_cache(status: CacheStatus) {
+    with_record(|record| record.metadata_cache = Some(status));
+}
+
+pub fn record_package_cache(status: CacheStatus) {
+    with_record(|record| record.package_cache = Some(status));
+}
+
+pub fn record_compressed_cache(status: CacheStatus) {
+    with_record(|record| record.compressed_cache = Some(status));
+}
+
+/// 记录一次上游请求（含重试）的耗时与响应体大小
+pub fn record_upstream(elapsed: Duration, bytes: usize) {
+    with_record(|record| {
+        record.upstream_requests += 1;
+        record.upstream_time += elapsed;
+        record.upstream_bytes += bytes as u64;
+    });
+}
+
+/// 为每个请求分配 ID、写访问日志，并附带 `X-Request-Id` / `X-Cache` / `X-Served-Version`
+///
+/// 客户端（或前置代理）传入合法的 `X-Request-Id` 时沿用，否则随机生成。
+pub async fn middleware(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(generate_request_id);
+
+    let method = request.method().to_string();
+    let uri = request.uri().to_string();
+    let user_agent = header_str(request.headers(), header::USER_AGENT);
+    let referer = header_str(request.headers(), header::REFERER);
+
+    let context = Arc::new(RequestContext {
+        id: id.clone(),
+        record: Mutex::default(),
+    });
+    let span =
+        tracing::info_span!("request", id = %id, method = %request.method(), uri = %request.uri());
+    telemetry::set_parent_from(&span, request.headers());
+    let mut response = CONTEXT
+        .scope(context.clone(), next.run(request).instrument(span))
+        .await;
+
+    let record = std::mem::take(&mut *context.record.lock().unwrap());
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        headers.insert(X_REQUEST_ID.clone(), value);
+    }
+    if let Some(version) = record.version.as_deref() {
+        if let Ok(value) = HeaderValue::from_str(version) {
+            headers.insert(X_SERVED_VERSION.clone(), value);
+        }
+    }
+    if let Some(hit) = record.cache_hit() {
+        headers.insert(
+            X_CACHE.clone(),
+            HeaderValue::from_static(if hit { "HIT" } else { "MISS" }),
+        );
+    }
+
+    if tracing::enabled!(target: TARGET, tracing::Level::INFO) {
+        let mut line = Map::new();
+        line.insert("time".into(), json!(unix_millis()));
+        line.insert("request_id".into(), json!(id));
+        line.insert("method".into(), json!(method));
+        line.insert("uri".into(), json!(uri));
+        line.insert("status".into(), json!(response.status().as_u16()));
+        line.insert(
+            "duration_ms".into(),
+            json!(started.elapsed().as_secs_f64() * 1000.0),
+        );
+        if let Some(bytes) = header_str(response.headers(), header::CONTENT_LENGTH) {
+            line.insert("bytes".into(), json!(bytes.parse::<u64>().ok()));
+        }
+        line.insert("user_agent".into(), json!(user_agent));
+        line.insert("referer".into(), json!(referer));
+        record.write(&mut line);
+        let line = Value::Object(line).to_string();
+        tracing::info!(target: TARGET, "{}", line);
+    }
+
+    response
+}
+
+impl Record {
+    /// 请求是否无需访问上游（元信息与包文件均已缓存），未涉及包的请求返回 None
+    ///
+    /// 压缩缓存未命中只需要在本地压缩，不影响结果。
+    fn cache_hit(&self) -> Option<bool> {
+        let statuses = [self.metadata_cache, self.package_cache];
+        if statuses.iter().all(Option::is_none) {
+            return None;
+        }
+        Some(statuses.iter().flatten().all(CacheStatus::is_hit))
+    }
+
+    fn write(&self, line: &mut Map<String, Value>) {
+        line.insert("client".into(), json!(self.client.map(|ip| ip.to_string())));
+        line.insert("package".into(), json!(self.package));
+        line.insert("version".into(), json!(self.version));
+
+        let mut cache = Map::new();
+        for (tier, status) in [
+            ("metadata", self.metadata_cache),
+            ("package", self.package_cache),
+            ("compressed", self.compressed_cache),
+        ] {
+            if let Some(status) = status {
+                cache.insert(tier.into(), json!(status.as_str()));
+            }
+        }
+        line.insert("cache".into(), Value::Object(cache));
+
+        if self.upstream_requests > 0 {
+            line.insert(
+                "upstream".into(),
+                json!({
+                    "requests": self.upstream_requests,
+                    "duration_ms": self.upstream_time.as_secs_f64() * 1000.0,
+                    "bytes": self.upstream_bytes,
+                }),
+            );
+        }
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+fn generate_request_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    format!("{:016x}", hasher.finish())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// 将访问日志按原样（每行一个 JSON 对象）写到标准输出的日志层
+///
+/// 普通日志层应过滤掉 [`TARGET`]，避免重复输出。
+pub struct JsonLines;
+
+impl<S: Subscriber> Layer<S> for JsonLines {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != TARGET {
+            return;
+        }
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let _ = writeln!(std::io::stdout().lock(), "{}", message.0);
+    }
+}
+
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn handler() -> String {
+        record_package("vue", "3.3.4");
+        record_metadata_cache(CacheStatus::Hit);
+        record_package_cache(CacheStatus::Pinned);
+        request_id().unwrap()
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/vue", get(handler))
+            .route("/", get(|| async { "home" }))
+            .layer(axum::middleware::from_fn(middleware))
+    }
+
+    #[tokio::test]
+    async fn test_response_headers() {
+        let response = app()
+            .oneshot(Request::get("/vue").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let headers = response.headers().clone();
+        assert_eq!(headers[&X_CACHE], "HIT");
+        assert_eq!(headers[&X_SERVED_VERSION], "3.3.4");
+
+        // 处理请求时看到的 ID 与响应头一致
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(headers[&X_REQUEST_ID].as_bytes(), &body[..]);
+
+        let response = app()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.headers().contains_key(&X_REQUEST_ID));
+        assert!(!response.headers().contains_key(&X_CACHE));
+    }
+
+    #[tokio::test]
+    async fn test_request_id_propagation() {
+        let request = |id: &str| {
+            Request::get("/vue")
+                .header(&X_REQUEST_ID, id)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app().oneshot(request("edge-42")).await.unwrap();
+        assert_eq!(response.headers()[&X_REQUEST_ID], "edge-42");
+
+        // 不合法的 ID 被替换
+        let response = app().oneshot(request("a b")).await.unwrap();
+        assert_ne!(response.headers()[&X_REQUEST_ID], "a b");
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let mut record = Record::default();
+        assert_eq!(record.cache_hit(), None);
+        record.metadata_cache = Some(CacheStatus::Hit);
+        record.package_cache = Some(CacheStatus::Hit);
+        assert_eq!(record.cache_hit(), Some(true));
+        record.compressed_cache = Some(CacheStatus::Miss);
+        assert_eq!(record.cache_hit(), Some(true));
+        record.package_cache = Some(CacheStatus::Miss);
+        assert_eq!(record.cache_hit(), Some(false));
+    }
+}
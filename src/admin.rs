@@ -0,0 +1,562 @@
+use crate::cache::{self, CacheManager};
+use crate::error::AppError;
+use crate::source::SourceRegistry;
+use crate::{changes, prewarm};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// 管理接口的共享状态
+#[derive(Clone)]
+pub struct AdminState {
+    /// `ADMIN_TOKEN`，未设置时管理接口不可用
+    pub token: Option<Arc<str>>,
+    /// `REGISTRY_HOOK_SECRET`，用于校验 npm hooks 的签名
+    pub hook_secret: Option<Arc<str>>,
+    pub sources: Arc<SourceRegistry>,
+    pub cache: Arc<CacheManager>,
+    /// 通过管理接口提交的后台预热任务
+    pub prewarm_jobs: Arc<prewarm::PrewarmJobs>,
+}
+
+/// 管理接口路由，挂载在 `/-/admin/` 下
+pub fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/-/admin/prewarm", post(prewarm_handler))
+        .route("/-/admin/prewarm/:id", get(prewarm_status_handler))
+        .route("/-/admin/cache", get(list_handler))
+        .route("/-/admin/cache/*spec", delete(purge_handler))
+        .route("/-/admin/refresh/*name", post(refresh_handler))
+        .route("/-/admin/hooks/registry", post(hook_handler))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+struct PrewarmParams {
+    #[serde(default)]
+    pin: bool,
+}
+
+/// `POST /-/admin/prewarm?pin=true`，请求体为 lockfile 或 `pkg@ver` 列表
+///
+/// 预热在后台进行，立即返回 202 与任务编号，结果通过 `GET /-/admin/prewarm/{id}` 查询。
+async fn prewarm_handler(
+    State(state): State<AdminState>,
+    Query(params): Query<PrewarmParams>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<impl IntoResponse, AppError> {
+    authorize(&state, &headers)?;
+
+    let specs = prewarm::parse_manifest(&body)?;
+    let requested = specs.len();
+    let id = state
+        .prewarm_jobs
+        .spawn(state.sources, state.cache, specs, params.pin);
+    tracing::info!("Started prewarm job {} for {} packages", id, requested);
+
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, format!("/-/admin/prewarm/{}", id))],
+        Json(json!({
+            "id": id,
+            "status": "running",
+            "requested": requested,
+            "pinned": params.pin,
+        })),
+    ))
+}
+
+/// `GET /-/admin/prewarm/{id}`，查询后台预热任务的状态与结果
+async fn prewarm_status_handler(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    authorize(&state, &headers)?;
+
+    let job = state
+        .prewarm_jobs
+        .get(id)
+        .ok_or_else(|| AppError::NotFound(format!("Prewarm job {} not found", id)))?;
+
+    Ok(Json(match job {
+        Some(report) => {
+            let mut body = serde_json::to_value(report)?;
+            body["id"] = json!(id);
+            body["status"] = json!("done");
+            body
+        }
+        None => json!({ "id": id, "status": "running" }),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListParams {
+    /// 只列出匹配该模式的条目，格式同清除接口
+    pattern: Option<String>,
+}
+
+/// `GET /-/admin/cache?pattern=vue@3.*`，列出缓存的元信息与包文件
+async fn list_handler(
+    State(state): State<AdminState>,
+    Query(params): Query<ListParams>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    authorize(&state, &headers)?;
+
+    let pattern = params.pattern.as_deref().map(SpecPattern::parse);
+    let entries = |entries: Vec<cache::EntryInfo>| -> Vec<Value> {
+        entries
+            .into_iter()
+            .filter(|entry| pattern.as_ref().map_or(true, |p| p.matches_key(&entry.key)))
+            .map(|entry| {
+                json!({
+                    "key": entry.key,
+                    "size": entry.size,
+                    "age": entry.age.as_secs(),
+                    "pinned": entry.pinned,
+                })
+            })
+            .collect()
+    };
+
+    let (file_entries, file_bytes) = state.cache.file_stats();
+    let (compressed_entries, compressed_bytes) = state.cache.compressed_stats();
+    Ok(Json(json!({
+        "metadata": entries(state.cache.list_metadata()),
+        "packages": entries(state.cache.list_packages()),
+        "files": {
+            "entries": file_entries,
+            "size": file_bytes,
+        },
+        "compressed": {
+            "entries": compressed_entries,
+            "size": compressed_bytes,
+        },
+    })))
+}
+
+/// `DELETE /-/admin/cache/{spec}`，清除匹配的元信息、包文件与压缩缓存
+///
+/// `spec` 为 `name`（整个包）、`name@version` 或带 `*` 的模式，例如 `@vue/*`、`vue@3.*`，
+/// 可以带 `gh/`、`local/` 来源前缀。
+async fn purge_handler(
+    State(state): State<AdminState>,
+    Path(spec): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    authorize(&state, &headers)?;
+
+    let pattern = SpecPattern::parse(&spec);
+    let purged = state.cache.purge(|key| pattern.matches_key(key)).await;
+    tracing::info!("Purged {} cache entries matching {}", purged, spec);
+
+    Ok(Json(json!({ "spec": spec, "purged": purged })))
+}
+
+/// `POST /-/admin/refresh/{name}`，立即重新获取包的元信息（例如刚发布的 `latest`）
+async fn refresh_handler(
+    State(state): State<AdminState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    authorize(&state, &headers)?;
+
+    let (prefix, source, rest) = state.sources.route(&name);
+    let (name, _, _) = source.parse_path(rest)?;
+
+    state
+        .cache
+        .invalidate_metadata(&source.metadata_cache_key(&name))
+        .await;
+    let latest = source.resolve_version(&name, None).await?;
+    tracing::info!(
+        "Refreshed metadata for {}{}, latest is {}",
+        prefix,
+        name,
+        latest
+    );
+
+    Ok(Json(
+        json!({ "name": format!("{}{}", prefix, name), "latest": latest }),
+    ))
+}
+
+/// `POST /-/admin/hooks/registry`，接收 npm hooks / Verdaccio 的发布通知
+///
+/// 带 `x-npm-signature` 时使用 `REGISTRY_HOOK_SECRET` 校验签名，否则要求管理令牌。
+async fn hook_handler(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Json<Value>, AppError> {
+    let signature = headers.get("x-npm-signature").and_then(|v| v.to_str().ok());
+    match (&state.hook_secret, signature) {
+        (Some(secret), Some(signature)) => {
+            if !changes::verify_signature(secret, &body, signature) {
+                return Err(AppError::Unauthorized("Invalid hook signature".to_string()));
+            }
+        }
+        _ => authorize(&state, &headers)?,
+    }
+
+    let payload: Value = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid hook payload: {}", e)))?;
+    let name = changes::hook_package_name(&payload)
+        .ok_or_else(|| AppError::InvalidRequest("Hook payload has no package name".to_string()))?;
+
+    let source = state
+        .sources
+        .get("npm")
+        .ok_or_else(|| AppError::NotFound("npm source is not configured".to_string()))?;
+    let refreshed = changes::refresh_if_cached(&state.cache, source.as_ref(), name).await;
+    tracing::info!(
+        "Registry hook for {} ({})",
+        name,
+        payload
+            .get("event")
+            .and_then(|v| v.as_str())
+            .unwrap_or("change")
+    );
+
+    Ok(Json(json!({ "name": name, "refreshed": refreshed })))
+}
+
+/// 清除与列表接口使用的 `name[@version]` 模式，`*` 匹配任意字符
+#[derive(Debug)]
+struct SpecPattern {
+    name: String,
+    version: Option<String>,
+}
+
+impl SpecPattern {
+    fn parse(spec: &str) -> Self {
+        let spec = spec.trim_start_matches('/');
+        // npm 包的缓存键不带来源前缀
+        let spec = spec.strip_prefix("npm/").unwrap_or(spec);
+        let (name, version) = split_version(spec);
+        Self {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+        }
+    }
+
+    /// 缓存键形如 `metadata:{name}`、`package:{name}@{version}` 或
+    /// `package:{name}@{version}/{file}:{encoding}`（压缩缓存）
+    fn matches_key(&self, key: &str) -> bool {
+        let (name, version) = if let Some(name) = key.strip_prefix("metadata:") {
+            (name, None)
+        } else if let Some(rest) = key.strip_prefix("package:") {
+            match split_version(rest) {
+                (name, Some(version)) => (name, Some(version.split('/').next().unwrap_or(""))),
+                (_, None) => return false,
+            }
+        } else {
+            return false;
+        };
+
+        glob_match(&self.name, name)
+            && match &self.version {
+                None => true,
+                Some(pattern) => version.is_some_and(|v| glob_match(pattern, v)),
+            }
+    }
+}
+
+/// 在包名之后的 `@` 处拆分（跳过 scope 开头的 `@`）
+fn split_version(spec: &str) -> (&str, Option<&str>) {
+    match spec.char_indices().skip(1).find(|(_, c)| *c == '@') {
+        Some((pos, _)) => (&spec[..pos], Some(&spec[pos + 1..])),
+        None => (spec, None),
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 `*` 的位置及其当前匹配到的文本位置
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// 校验 `Authorization: Bearer <ADMIN_TOKEN>`
+fn authorize(state: &AdminState, headers: &HeaderMap) -> Result<(), AppError> {
+    let Some(expected) = &state.token else {
+        return Err(AppError::NotFound("Admin API is disabled".to_string()));
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+
+    if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized(
+            "Missing or invalid admin token".to_string(),
+        ))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local::LocalSource;
+    use crate::source::PackageSource;
+    use crate::testutil::write_tarball;
+    use axum::{body::Body, http::Request, http::StatusCode};
+    use tower::ServiceExt;
+
+    fn state(token: Option<&str>, root: &std::path::Path) -> AdminState {
+        let cache = Arc::new(CacheManager::new());
+        let local = Arc::new(LocalSource::new(root, cache.clone()));
+        let mut sources = SourceRegistry::new(local.clone());
+        sources.register("npm", local);
+        AdminState {
+            token: token.map(Arc::from),
+            hook_secret: Some(Arc::from("hook-secret")),
+            sources: Arc::new(sources),
+            cache,
+            prewarm_jobs: Arc::default(),
+        }
+    }
+
+    fn request(token: Option<&str>, body: &str) -> Request<Body> {
+        let mut builder = Request::post("/-/admin/prewarm?pin=true");
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_prewarm_requires_token() {
+        let root = tempfile::tempdir().unwrap();
+
+        let disabled = router(state(None, root.path()))
+            .oneshot(request(Some("x"), "vue@1.0.0"))
+            .await
+            .unwrap();
+        assert_eq!(disabled.status(), StatusCode::NOT_FOUND);
+
+        let denied = router(state(Some("secret"), root.path()))
+            .oneshot(request(Some("wrong"), "vue@1.0.0"))
+            .await
+            .unwrap();
+        assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    async fn json_body(response: axum::response::Response) -> Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// 轮询后台预热任务直到完成
+    async fn finished_job(app: &Router, id: u64) -> Value {
+        loop {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get(format!("/-/admin/prewarm/{}", id))
+                        .header(header::AUTHORIZATION, "Bearer secret")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = json_body(response).await;
+            if body["status"] == "done" {
+                return body;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prewarm_pins_packages() {
+        let root = tempfile::tempdir().unwrap();
+        write_tarball(&root.path().join("demo-1.0.0.tgz"), "demo", "1.0.0");
+
+        let state = state(Some("secret"), root.path());
+        let cache = state.cache.clone();
+        let app = router(state);
+        let response = app
+            .clone()
+            .oneshot(request(Some("secret"), "demo@^1\nmissing@1.0.0\n"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers()[header::LOCATION], "/-/admin/prewarm/1");
+        let accepted = json_body(response).await;
+        assert_eq!(accepted["requested"], 2);
+
+        let report = finished_job(&app, 1).await;
+        assert_eq!(report["warmed"], serde_json::json!(["demo@1.0.0"]));
+        assert_eq!(report["failed"][0]["spec"], "missing@1.0.0");
+        assert!(cache.is_pinned("package:local/demo@1.0.0"));
+
+        let unknown = app
+            .oneshot(
+                Request::get("/-/admin/prewarm/99")
+                    .header(header::AUTHORIZATION, "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+
+        assert!(cache
+            .get_package("package:local/demo@1.0.0")
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_purge_and_refresh() {
+        let root = tempfile::tempdir().unwrap();
+        write_tarball(&root.path().join("demo-1.0.0.tgz"), "demo", "1.0.0");
+
+        let state = state(Some("secret"), root.path());
+        let cache = state.cache.clone();
+        let app = router(state);
+        let send = |method: &str, uri: &str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        app.clone()
+            .oneshot(request(Some("secret"), "demo@1.0.0"))
+            .await
+            .unwrap();
+        finished_job(&app, 1).await;
+        let listing = json_body(
+            send("GET", "/-/admin/cache?pattern=local/demo")
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(listing["metadata"][0]["key"], "metadata:local/demo");
+        assert_eq!(listing["packages"][0]["key"], "package:local/demo@1.0.0");
+        assert_eq!(listing["packages"][0]["pinned"], true);
+
+        // 发布新版本后元信息仍在缓存中，刷新后立即可见
+        write_tarball(&root.path().join("demo-1.1.0.tgz"), "demo", "1.1.0");
+        let refreshed = json_body(send("POST", "/-/admin/refresh/demo").await.unwrap()).await;
+        assert_eq!(refreshed["latest"], "1.1.0");
+
+        let purged = json_body(
+            send("DELETE", "/-/admin/cache/local/demo@1.*")
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(purged["purged"], 1);
+        assert!(cache
+            .get_package("package:local/demo@1.0.0")
+            .await
+            .is_none());
+        assert!(cache.get_metadata("metadata:local/demo").await.is_some());
+    }
+
+    #[test]
+    fn test_spec_pattern() {
+        let matches = |spec: &str, key: &str| SpecPattern::parse(spec).matches_key(key);
+
+        assert!(matches("vue", "metadata:vue"));
+        assert!(matches("npm/vue", "package:vue@3.3.4"));
+        assert!(matches("vue", "package:vue@3.3.4/dist/vue.js:br"));
+        assert!(!matches("vue", "package:vue-router@4.0.0"));
+        assert!(matches("vue@3.*", "package:vue@3.3.4"));
+        assert!(!matches("vue@3.*", "metadata:vue"));
+        assert!(!matches("vue@3.*", "package:vue@2.7.14"));
+        assert!(matches("@vue/*", "package:@vue/shared@3.3.4/index.js:gzip"));
+        assert!(matches("@vue/shared@3.3.4", "package:@vue/shared@3.3.4"));
+        assert!(matches("gh/jquery/*", "metadata:gh/jquery/jquery"));
+        assert!(!matches("jquery/*", "metadata:gh/jquery/jquery"));
+        assert!(matches("*", "package:local/demo@1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn test_registry_hook() {
+        use hmac::{Hmac, Mac};
+
+        let root = tempfile::tempdir().unwrap();
+        write_tarball(&root.path().join("demo-1.0.0.tgz"), "demo", "1.0.0");
+        let state = state(None, root.path());
+        let cache = state.cache.clone();
+        let app = router(state);
+
+        let hook = |body: &'static str, signature: String| {
+            app.clone().oneshot(
+                Request::post("/-/admin/hooks/registry")
+                    .header("x-npm-signature", signature)
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+        let sign = |body: &str| {
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"hook-secret").unwrap();
+            mac.update(body.as_bytes());
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+        };
+
+        let body = r#"{"event":"package:publish","name":"demo"}"#;
+        let denied = hook(body, sign("tampered")).await.unwrap();
+        assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+
+        // 未缓存的包无需刷新
+        let skipped = json_body(hook(body, sign(body)).await.unwrap()).await;
+        assert_eq!(skipped["refreshed"], false);
+
+        // 已缓存的包立即重新获取元信息
+        let source = LocalSource::new(root.path(), cache.clone());
+        source.resolve_version("demo", None).await.unwrap();
+        write_tarball(&root.path().join("demo-1.1.0.tgz"), "demo", "1.1.0");
+
+        let refreshed = json_body(hook(body, sign(body)).await.unwrap()).await;
+        assert_eq!(refreshed["refreshed"], true);
+        let metadata = cache.get_metadata("metadata:local/demo").await.unwrap();
+        assert_eq!(metadata["dist-tags"]["latest"], "1.1.0");
+    }
+}
@@ -1,49 +1,282 @@
+use crate::error::AppError;
+use crate::tarball;
+use bytes::Bytes;
 use moka::future::Cache;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// 包文件占用的内存总量
+const PACKAGE_CAPACITY: u64 = 512 * 1024 * 1024; // 512 MB
+/// 其中留给固定包的部分，其余由 LRU 缓存使用
+const PINNED_CAPACITY: u64 = 128 * 1024 * 1024; // 128 MB
 
 pub struct CacheManager {
     // 元信息缓存 (5分钟)
-    metadata_cache: Cache<String, Arc<Value>>,
-    // 包文件缓存 (1小时)
-    package_cache: Cache<String, Arc<PackageData>>,
+    metadata_cache: Cache<String, Entry<Value>>,
+    // 包文件缓存 (1小时，按压缩后的字节数计算容量)
+    package_cache: Cache<String, Entry<PackageData>>,
+    // 从压缩包中解压出的热点文件 (按字节数计算容量)
+    file_cache: Cache<String, Bytes>,
+    // 压缩后的文件缓存 (按字节数计算容量)
+    compressed_cache: Cache<String, Bytes>,
+    // 固定的包文件 (不会过期或被淘汰)
+    pinned_packages: RwLock<HashMap<String, Entry<PackageData>>>,
 }
 
+/// 包内容
+///
+/// 只保存分块压缩的文件内容和索引，请求某个文件时才解压所在的块，
+/// 解压出的热点文件另外缓存（见 [`CacheManager::get_file`]）。
+/// 包本身以 `Arc<PackageData>` 在缓存与请求之间共享。
 #[derive(Clone)]
 pub struct PackageData {
-    pub files: HashMap<String, Vec<u8>>,
+    pub files: tarball::Index,
     pub package_json: Value,
 }
 
+impl PackageData {
+    /// 占用的字节数（压缩后的大小）
+    pub fn size(&self) -> usize {
+        self.files.size()
+    }
+}
+
+/// 缓存值及其写入时间
+#[derive(Clone)]
+struct Entry<T> {
+    value: Arc<T>,
+    inserted_at: Instant,
+}
+
+impl<T> Entry<T> {
+    fn new(value: Arc<T>) -> Self {
+        Self {
+            value,
+            inserted_at: Instant::now(),
+        }
+    }
+}
+
+/// 管理接口展示的缓存条目
+#[derive(Debug)]
+pub struct EntryInfo {
+    pub key: String,
+    pub size: usize,
+    pub age: Duration,
+    pub pinned: bool,
+}
+
 impl CacheManager {
     pub fn new() -> Self {
+        Self::with_metadata_ttl(Duration::from_secs(300)) // 5 minutes
+    }
+
+    /// 指定元信息缓存时长，订阅了 registry 变更时可以设置得更长
+    pub fn with_metadata_ttl(metadata_ttl: Duration) -> Self {
         Self {
             metadata_cache: Cache::builder()
                 .max_capacity(1000)
-                .time_to_live(Duration::from_secs(300)) // 5 minutes
+                .time_to_live(metadata_ttl)
                 .build(),
             package_cache: Cache::builder()
-                .max_capacity(500)
+                .weigher(|_key: &String, entry: &Entry<PackageData>| {
+                    entry.value.size().try_into().unwrap_or(u32::MAX)
+                })
+                .max_capacity(PACKAGE_CAPACITY - PINNED_CAPACITY)
                 .time_to_live(Duration::from_secs(3600)) // 1 hour
                 .build(),
+            file_cache: Cache::builder()
+                .weigher(|_key: &String, value: &Bytes| value.len().try_into().unwrap_or(u32::MAX))
+                .max_capacity(256 * 1024 * 1024) // 256 MB
+                .time_to_live(Duration::from_secs(3600)) // 1 hour
+                .build(),
+            compressed_cache: Cache::builder()
+                .weigher(|_key: &String, value: &Bytes| value.len().try_into().unwrap_or(u32::MAX))
+                .max_capacity(256 * 1024 * 1024) // 256 MB
+                .time_to_live(Duration::from_secs(3600)) // 1 hour
+                .build(),
+            pinned_packages: RwLock::new(HashMap::new()),
         }
     }
 
     pub async fn get_metadata(&self, key: &str) -> Option<Arc<Value>> {
-        self.metadata_cache.get(key).await
+        self.metadata_cache.get(key).await.map(|entry| entry.value)
     }
 
     pub async fn set_metadata(&self, key: String, value: Value) {
-        self.metadata_cache.insert(key, Arc::new(value)).await;
+        self.metadata_cache
+            .insert(key, Entry::new(Arc::new(value)))
+            .await;
+    }
+
+    /// 元信息是否已缓存（不影响淘汰顺序）
+    pub fn contains_metadata(&self, key: &str) -> bool {
+        self.metadata_cache.contains_key(key)
+    }
+
+    /// 包文件是否已缓存或已固定（不影响淘汰顺序）
+    pub fn contains_package(&self, key: &str) -> bool {
+        self.pinned_packages.read().unwrap().contains_key(key)
+            || self.package_cache.contains_key(key)
+    }
+
+    /// 包文件是否已固定
+    pub fn is_pinned(&self, key: &str) -> bool {
+        self.pinned_packages.read().unwrap().contains_key(key)
     }
 
     pub async fn get_package(&self, key: &str) -> Option<Arc<PackageData>> {
-        self.package_cache.get(key).await
+        if let Some(pinned) = self.pinned_packages.read().unwrap().get(key) {
+            return Some(pinned.value.clone());
+        }
+        self.package_cache.get(key).await.map(|entry| entry.value)
+    }
+
+    pub async fn set_package(&self, key: String, value: Arc<PackageData>) {
+        self.package_cache.insert(key, Entry::new(value)).await;
+    }
+
+    /// 固定包文件，使其不受缓存淘汰与过期的影响
+    ///
+    /// 固定的包总大小不超过 [`PINNED_CAPACITY`]，超出时返回错误，包仍留在普通缓存中。
+    pub async fn pin_package(&self, key: String, value: Arc<PackageData>) -> Result<(), AppError> {
+        {
+            let mut pinned = self.pinned_packages.write().unwrap();
+            let used: u64 = pinned
+                .iter()
+                .filter(|(pinned_key, _)| **pinned_key != key)
+                .map(|(_, entry)| entry.value.size() as u64)
+                .sum();
+            if used + value.size() as u64 > PINNED_CAPACITY {
+                return Err(AppError::TooLarge(format!(
+                    "Pinned packages would exceed {} MB",
+                    PINNED_CAPACITY / 1024 / 1024
+                )));
+            }
+            pinned.insert(key.clone(), Entry::new(value));
+        }
+
+        // 固定后不再占用 LRU 缓存的容量
+        self.package_cache.invalidate(&key).await;
+        Ok(())
+    }
+
+    /// 取出已解压的文件，键为 `{package_key}/{file}`
+    pub async fn get_file(&self, key: &str) -> Option<Bytes> {
+        self.file_cache.get(key).await
+    }
+
+    pub async fn set_file(&self, key: String, value: Bytes) {
+        self.file_cache.insert(key, value).await;
+    }
+
+    pub async fn get_compressed(&self, key: &str) -> Option<Bytes> {
+        self.compressed_cache.get(key).await
+    }
+
+    pub async fn set_compressed(&self, key: String, value: Bytes) {
+        self.compressed_cache.insert(key, value).await;
+    }
+
+    /// 列出元信息缓存（大小为 JSON 序列化后的字节数）
+    pub fn list_metadata(&self) -> Vec<EntryInfo> {
+        let mut entries: Vec<EntryInfo> = self
+            .metadata_cache
+            .iter()
+            .map(|(key, entry)| EntryInfo {
+                key: key.to_string(),
+                size: serde_json::to_vec(&*entry.value).map_or(0, |v| v.len()),
+                age: entry.inserted_at.elapsed(),
+                pinned: false,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    }
+
+    /// 列出包文件缓存，包括固定的包
+    pub fn list_packages(&self) -> Vec<EntryInfo> {
+        let info = |key: String, entry: &Entry<PackageData>, pinned: bool| EntryInfo {
+            key,
+            size: entry.value.size(),
+            age: entry.inserted_at.elapsed(),
+            pinned,
+        };
+
+        let pinned = self.pinned_packages.read().unwrap();
+        let mut entries: Vec<EntryInfo> = pinned
+            .iter()
+            .map(|(key, entry)| info(key.clone(), entry, true))
+            .collect();
+        for (key, entry) in self.package_cache.iter() {
+            if !pinned.contains_key(key.as_str()) {
+                entries.push(info(key.to_string(), &entry, false));
+            }
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    }
+
+    /// 已解压文件缓存的 (条目数, 字节数)
+    pub fn file_stats(&self) -> (u64, u64) {
+        (
+            self.file_cache.entry_count(),
+            self.file_cache.weighted_size(),
+        )
+    }
+
+    /// 压缩缓存的 (条目数, 字节数)
+    pub fn compressed_stats(&self) -> (u64, u64) {
+        (
+            self.compressed_cache.entry_count(),
+            self.compressed_cache.weighted_size(),
+        )
+    }
+
+    /// 删除所有键满足条件的缓存（包括固定的包），返回删除的条目数
+    ///
+    /// 文件缓存与压缩缓存的键以所属包的缓存键开头
+    /// （`{package_key}/{file}`、`{package_key}/{file}:{encoding}`）。
+    pub async fn purge(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut purged = HashSet::new();
+
+        self.pinned_packages.write().unwrap().retain(|key, _| {
+            let keep = !matches(key);
+            if !keep {
+                purged.insert(key.clone());
+            }
+            keep
+        });
+
+        let metadata: Vec<_> = self.metadata_cache.iter().map(|(key, _)| key).collect();
+        let packages: Vec<_> = self.package_cache.iter().map(|(key, _)| key).collect();
+        let files: Vec<_> = self.file_cache.iter().map(|(key, _)| key).collect();
+        let compressed: Vec<_> = self.compressed_cache.iter().map(|(key, _)| key).collect();
+
+        for key in metadata.iter().filter(|key| matches(key)) {
+            self.metadata_cache.invalidate(key.as_str()).await;
+            purged.insert(key.to_string());
+        }
+        for key in packages.iter().filter(|key| matches(key)) {
+            self.package_cache.invalidate(key.as_str()).await;
+            purged.insert(key.to_string());
+        }
+        for key in files.iter().filter(|key| matches(key)) {
+            self.file_cache.invalidate(key.as_str()).await;
+            purged.insert(key.to_string());
+        }
+        for key in compressed.iter().filter(|key| matches(key)) {
+            self.compressed_cache.invalidate(key.as_str()).await;
+            purged.insert(key.to_string());
+        }
+
+        purged.len()
     }
 
-    pub async fn set_package(&self, key: String, value: PackageData) {
-        self.package_cache.insert(key, Arc::new(value)).await;
+    /// 使元信息缓存失效，下次请求时重新获取
+    pub async fn invalidate_metadata(&self, key: &str) {
+        self.metadata_cache.invalidate(key).await;
     }
 }
@@ -0,0 +1,260 @@
+use crate::cache::CacheManager;
+use crate::error::AppError;
+use crate::source::PackageSource;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde_json::Value;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 长轮询等待时间，registry 在这段时间内没有变更时返回空结果
+const LONGPOLL_TIMEOUT: Duration = Duration::from_secs(30);
+/// 请求失败后的重试间隔
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// 每次最多处理的变更数
+const BATCH_LIMIT: usize = 500;
+
+/// 包有变更时更新缓存：已缓存元信息的包立即重新获取，未缓存的包无需处理
+///
+/// 返回是否刷新了缓存。重新获取失败（例如包已被撤销）时元信息保持失效状态。
+pub async fn refresh_if_cached(
+    cache: &CacheManager,
+    source: &dyn PackageSource,
+    name: &str,
+) -> bool {
+    let key = source.metadata_cache_key(name);
+    if !cache.contains_metadata(&key) {
+        return false;
+    }
+
+    cache.invalidate_metadata(&key).await;
+    match source.resolve_version(name, None).await {
+        Ok(latest) => tracing::debug!("Refreshed metadata for {}, latest is {}", name, latest),
+        Err(e) => tracing::debug!("Invalidated metadata for {}: {}", name, e),
+    }
+    true
+}
+
+/// CouchDB 复制协议风格的 `_changes` 订阅（npm replicate、Verdaccio 等）
+pub struct ChangesFollower {
+    client: Client,
+    url: String,
+    source: Arc<dyn PackageSource>,
+    cache: Arc<CacheManager>,
+}
+
+impl ChangesFollower {
+    pub fn new(
+        client: Client,
+        url: impl Into<String>,
+        source: Arc<dyn PackageSource>,
+        cache: Arc<CacheManager>,
+    ) -> Self {
+        Self {
+            client,
+            url: url.into(),
+            source,
+            cache,
+        }
+    }
+
+    /// 持续跟随变更，`since` 为起始序号（`now` 表示只关心之后的变更）
+    pub async fn run(self, mut since: String) {
+        tracing::info!(
+            "Following registry changes from {} (since {})",
+            self.url,
+            since
+        );
+
+        loop {
+            match self.poll_once(&since).await {
+                Ok(next) => since = next,
+                Err(e) => {
+                    tracing::warn!("Registry changes feed failed: {}", e);
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+
+    /// 拉取一批变更并刷新对应的缓存，返回下一次请求使用的序号
+    pub async fn poll_once(&self, since: &str) -> Result<String, AppError> {
+        let timeout = LONGPOLL_TIMEOUT.as_millis().to_string();
+        let limit = BATCH_LIMIT.to_string();
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[
+                ("feed", "longpoll"),
+                ("since", since),
+                ("timeout", timeout.as_str()),
+                ("limit", limit.as_str()),
+            ])
+            .timeout(LONGPOLL_TIMEOUT + Duration::from_secs(10))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(AppError::InternalError(format!(
+                "Changes feed returned {}",
+                response.status()
+            )));
+        }
+
+        let body: Value = response.json().await?;
+        let (names, last_seq) = parse_changes(&body)?;
+
+        let mut refreshed = 0;
+        for name in &names {
+            if refresh_if_cached(&self.cache, self.source.as_ref(), name).await {
+                refreshed += 1;
+            }
+        }
+        if !names.is_empty() {
+            tracing::debug!(
+                "Processed {} registry changes ({} cached packages refreshed)",
+                names.len(),
+                refreshed
+            );
+        }
+
+        Ok(last_seq.unwrap_or_else(|| since.to_string()))
+    }
+}
+
+/// 解析 `{"results": [{"seq": .., "id": "name"}], "last_seq": ..}`
+///
+/// 序号可能是数字或字符串（CouchDB 2+），统一按字符串原样传回。
+fn parse_changes(body: &Value) -> Result<(Vec<String>, Option<String>), AppError> {
+    let results = body
+        .get("results")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| AppError::InternalError("Invalid changes feed response".to_string()))?;
+
+    let names = results
+        .iter()
+        .filter_map(|change| change.get("id").and_then(|v| v.as_str()))
+        .filter(|id| !id.starts_with("_design/"))
+        .map(str::to_string)
+        .collect();
+
+    let last_seq = body.get("last_seq").and_then(|seq| match seq {
+        Value::String(seq) => Some(seq.clone()),
+        Value::Number(seq) => Some(seq.to_string()),
+        _ => None,
+    });
+
+    Ok((names, last_seq))
+}
+
+/// 从 npm hooks 或 Verdaccio 通知的请求体中取出包名
+///
+/// npm hooks 形如 `{"event": "package:publish", "name": "vue", ...}`，
+/// Verdaccio 的通知内容可以自定义，这里接受顶层或 `package` 下的 `name`。
+pub fn hook_package_name(body: &Value) -> Option<&str> {
+    body.get("name")
+        .or_else(|| body.get("package").and_then(|p| p.get("name")))
+        .and_then(|v| v.as_str())
+}
+
+/// 校验 npm hooks 的 `x-npm-signature: sha256=<hex>`（请求体的 HMAC-SHA256）
+pub fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(expected) = signature
+        .strip_prefix("sha256=")
+        .and_then(|hex| hex::decode(hex).ok())
+    else {
+        return false;
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local::LocalSource;
+    use crate::testutil::write_tarball;
+    use axum::{extract::Query, routing::get, Json, Router};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_changes() {
+        let body = serde_json::json!({
+            "results": [
+                {"seq": 10, "id": "vue", "changes": [{"rev": "1-a"}]},
+                {"seq": 11, "id": "_design/app"},
+                {"seq": 12, "id": "@vue/shared"}
+            ],
+            "last_seq": 12
+        });
+        let (names, last_seq) = parse_changes(&body).unwrap();
+        assert_eq!(names, vec!["vue", "@vue/shared"]);
+        assert_eq!(last_seq.as_deref(), Some("12"));
+
+        let body = serde_json::json!({"results": [], "last_seq": "13-g1AAAA"});
+        assert_eq!(
+            parse_changes(&body).unwrap().1.as_deref(),
+            Some("13-g1AAAA")
+        );
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = br#"{"name":"vue"}"#;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        let valid = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(verify_signature("secret", body, &valid));
+        assert!(!verify_signature("other", body, &valid));
+        assert!(!verify_signature("secret", br#"{"name":"react"}"#, &valid));
+        assert!(!verify_signature("secret", body, "md5=00"));
+    }
+
+    #[tokio::test]
+    async fn test_follow_fake_feed() {
+        // 模拟 registry 的 _changes：since=0 时返回 demo 与未缓存的 other 的变更
+        let feed = Router::new().route(
+            "/_changes",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                let results = match params.get("since").map(String::as_str) {
+                    Some("0") => {
+                        serde_json::json!([{"seq": 1, "id": "demo"}, {"seq": 2, "id": "other"}])
+                    }
+                    _ => serde_json::json!([]),
+                };
+                Json(serde_json::json!({"results": results, "last_seq": 2}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, feed).await.unwrap() });
+
+        let root = tempfile::tempdir().unwrap();
+        write_tarball(&root.path().join("demo-1.0.0.tgz"), "demo", "1.0.0");
+        let cache = Arc::new(CacheManager::new());
+        let source: Arc<dyn PackageSource> = Arc::new(LocalSource::new(root.path(), cache.clone()));
+        assert_eq!(source.resolve_version("demo", None).await.unwrap(), "1.0.0");
+
+        // 新版本发布后，缓存中的元信息仍指向旧版本
+        write_tarball(&root.path().join("demo-1.1.0.tgz"), "demo", "1.1.0");
+        assert_eq!(source.resolve_version("demo", None).await.unwrap(), "1.0.0");
+
+        let follower = ChangesFollower::new(
+            Client::new(),
+            format!("http://{}/_changes", addr),
+            source.clone(),
+            cache.clone(),
+        );
+        assert_eq!(follower.poll_once("0").await.unwrap(), "2");
+
+        let latest = cache.get_metadata("metadata:local/demo").await.unwrap();
+        assert_eq!(latest["dist-tags"]["latest"], "1.1.0");
+        assert!(cache.get_metadata("metadata:local/other").await.is_none());
+    }
+}
@@ -0,0 +1,200 @@
+use axum::http::{header, HeaderMap};
+use std::io::Write;
+
+/// 小于该大小的文件不压缩
+pub const MIN_COMPRESS_SIZE: usize = 1024;
+
+/// 缓存的压缩结果使用最高压缩级别（每个文件只压缩一次）
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_WINDOW: u32 = 22;
+
+/// 动态生成的页面（目录列表）每次都要压缩，使用较快的级别
+const DYNAMIC_BROTLI_QUALITY: u32 = 5;
+
+/// 支持的内容编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Content-Encoding 响应头中的名称
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// 包内预压缩文件的扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => ".br",
+            Encoding::Gzip => ".gz",
+        }
+    }
+}
+
+/// 根据 Accept-Encoding 选择客户端可接受的编码，按偏好排序
+///
+/// q 值相同时 br 优先于 gzip，`q=0` 表示拒绝该编码。
+pub fn negotiate(headers: &HeaderMap) -> Vec<Encoding> {
+    let mut brotli: Option<f32> = None;
+    let mut gzip: Option<f32> = None;
+    let mut wildcard: Option<f32> = None;
+
+    for value in headers.get_all(header::ACCEPT_ENCODING) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match name.as_str() {
+                "br" => brotli = Some(q),
+                "gzip" | "x-gzip" => gzip = Some(q),
+                "*" => wildcard = Some(q),
+                _ => {}
+            }
+        }
+    }
+
+    let mut accepted: Vec<(Encoding, f32)> = [
+        (Encoding::Brotli, brotli.or(wildcard)),
+        (Encoding::Gzip, gzip.or(wildcard)),
+    ]
+    .into_iter()
+    .filter_map(|(encoding, q)| q.filter(|q| *q > 0.0).map(|q| (encoding, q)))
+    .collect();
+
+    // 稳定排序，保持 br 在 gzip 之前
+    accepted.sort_by(|a, b| b.1.total_cmp(&a.1));
+    accepted.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+/// 判断该 Content-Type 的内容是否值得压缩（图片、字体等已压缩格式除外）
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/javascript"
+                | "application/json"
+                | "application/xml"
+                | "application/wasm"
+                | "application/vnd.ms-fontobject"
+                | "image/svg+xml"
+                | "font/ttf"
+        )
+}
+
+/// 压缩数据（用于缓存的静态文件）
+pub fn compress(data: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    compress_with_level(data, encoding, BROTLI_QUALITY, flate2::Compression::best())
+}
+
+/// 快速压缩动态生成的内容
+pub fn compress_fast(data: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    compress_with_level(
+        data,
+        encoding,
+        DYNAMIC_BROTLI_QUALITY,
+        flate2::Compression::fast(),
+    )
+}
+
+fn compress_with_level(
+    data: &[u8],
+    encoding: Encoding,
+    brotli_quality: u32,
+    gzip_level: flate2::Compression,
+) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut output = Vec::with_capacity(data.len() / 3);
+            {
+                let mut writer =
+                    brotli::CompressorWriter::new(&mut output, 4096, brotli_quality, BROTLI_WINDOW);
+                writer.write_all(data)?;
+                writer.flush()?;
+            }
+            Ok(output)
+        }
+        Encoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::with_capacity(data.len() / 3), gzip_level);
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use std::io::Read;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            negotiate(&accept("gzip, deflate, br")),
+            vec![Encoding::Brotli, Encoding::Gzip]
+        );
+        assert_eq!(
+            negotiate(&accept("br;q=0.5, gzip")),
+            vec![Encoding::Gzip, Encoding::Brotli]
+        );
+        assert_eq!(negotiate(&accept("gzip, br;q=0")), vec![Encoding::Gzip]);
+        assert_eq!(
+            negotiate(&accept("*")),
+            vec![Encoding::Brotli, Encoding::Gzip]
+        );
+        assert_eq!(negotiate(&accept("identity")), vec![]);
+        assert_eq!(negotiate(&HeaderMap::new()), vec![]);
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let data = "console.log('hello');\n".repeat(200);
+
+        let gz = compress(data.as_bytes(), Encoding::Gzip).unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&gz[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let br = compress(data.as_bytes(), Encoding::Brotli).unwrap();
+        let mut decoded = String::new();
+        brotli::Decompressor::new(&br[..], 4096)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+        assert!(br.len() < data.len());
+    }
+
+    #[test]
+    fn test_is_compressible() {
+        assert!(is_compressible("application/javascript; charset=utf-8"));
+        assert!(is_compressible("text/css; charset=utf-8"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("font/woff2"));
+    }
+}
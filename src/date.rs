@@ -0,0 +1,171 @@
+/// 解析 `2024-01-01`、`2024-01-01T08:00:00Z`、`2024-01-01T08:00:00.000+08:00`
+/// 这类 ISO 8601 日期为 Unix 毫秒，只有日期时按 UTC 零点处理
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    fn number(s: &str, digits: usize) -> Option<i64> {
+        if s.len() == digits && s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    let s = s.trim();
+    let (date, time) = match s.split_once(['T', 't', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+
+    let mut date_parts = date.split('-');
+    let year = number(date_parts.next()?, 4)?;
+    let month = number(date_parts.next()?, 2)?;
+    let day = number(date_parts.next()?, 2)?;
+    if date_parts.next().is_some()
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+    {
+        return None;
+    }
+    let mut millis = days_from_civil(year, month, day) * 86_400_000;
+
+    if let Some(time) = time {
+        // 时区：Z 或 ±HH:MM，省略时按 UTC 处理
+        let (time, offset) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+            (time, 0)
+        } else if let Some(pos) = time.rfind(['+', '-']) {
+            let (hours, minutes) = time[pos + 1..].split_once(':')?;
+            let offset = number(hours, 2)? * 60 + number(minutes, 2)?;
+            let sign = if time[pos..].starts_with('-') { -1 } else { 1 };
+            (&time[..pos], sign * offset * 60_000)
+        } else {
+            (time, 0)
+        };
+
+        let (time, fraction) = match time.split_once('.') {
+            Some((time, fraction)) => (time, Some(fraction)),
+            None => (time, None),
+        };
+        let mut time_parts = time.split(':');
+        let hour = number(time_parts.next()?, 2)?;
+        let minute = number(time_parts.next()?, 2)?;
+        let second = match time_parts.next() {
+            Some(second) => number(second, 2)?,
+            None => 0,
+        };
+        if time_parts.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        let fraction_millis = match fraction {
+            Some(f) if !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()) => {
+                format!("{:0<3}", &f[..f.len().min(3)])
+                    .parse::<i64>()
+                    .ok()?
+            }
+            Some(_) => return None,
+            None => 0,
+        };
+        millis += ((hour * 60 + minute) * 60 + second) * 1000 + fraction_millis - offset;
+    }
+    Some(millis)
+}
+
+/// Unix 毫秒格式化为 `2024-01-01T00:00:00.000Z`
+pub fn format_timestamp(millis: i64) -> String {
+    let days = millis.div_euclid(86_400_000);
+    let rest = millis.rem_euclid(86_400_000);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rest / 3_600_000,
+        rest / 60_000 % 60,
+        rest / 1000 % 60,
+        rest % 1000
+    )
+}
+
+/// 公历中某月的天数
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 公历日期与天数的换算（Howard Hinnant 的 days_from_civil / civil_from_days）
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2024-01-01"), Some(1_704_067_200_000));
+        assert_eq!(
+            parse_timestamp("2024-01-01T00:00:00.000Z"),
+            Some(1_704_067_200_000)
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-01T08:30:15.5+08:00"),
+            Some(1_704_067_200_000 + (30 * 60 + 15) * 1000 + 500)
+        );
+        assert_eq!(
+            parse_timestamp("2023-12-31T19:00-05:00"),
+            Some(1_704_067_200_000)
+        );
+        assert_eq!(
+            parse_timestamp("2000-02-29T12:00:00"),
+            Some(951_825_600_000)
+        );
+        assert_eq!(parse_timestamp("2024-02-29"), Some(1_709_164_800_000));
+        assert_eq!(parse_timestamp("2023-12-31"), Some(1_703_980_800_000));
+        for invalid in [
+            "",
+            "2024",
+            "2024-1-1",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-01-00",
+            "2024-01-32",
+            "2024-02-30",
+            "2024-02-31",
+            "2023-02-29",
+            "1900-02-29",
+            "2023-04-31",
+            "2023-11-31",
+            "2024-01-01T25:00",
+            "yesterday",
+        ] {
+            assert_eq!(parse_timestamp(invalid), None, "{}", invalid);
+        }
+        assert_eq!(
+            format_timestamp(1_704_067_200_123),
+            "2024-01-01T00:00:00.123Z"
+        );
+    }
+}
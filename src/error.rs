@@ -1,46 +1,230 @@
 use axum::{
-    http::StatusCode,
+    body::Body,
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response},
 };
 use std::fmt;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum AppError {
+    /// 文件等资源不存在
     NotFound(String),
+    /// 包（或仓库）不存在
+    PackageNotFound(String),
+    /// 包存在，但没有匹配的版本（或引用）
+    VersionNotFound(String),
     InternalError(String),
     InvalidRequest(String),
+    InvalidArchive(String),
+    Unauthorized(String),
+    /// 被服务端策略拒绝
+    Forbidden(String),
+    /// 包或文件超过大小限制
+    TooLarge(String),
+    /// 上游返回错误或无法连接（502）
+    UpstreamError(String),
+    /// 上游超时（504）
+    UpstreamTimeout(String),
+    /// 上游处于熔断状态（503）
+    UpstreamUnavailable(String),
+    /// 上游以 429 限制了我们的请求（503），附带上游建议的重试等待时间
+    UpstreamRateLimited(String, Option<Duration>),
+    /// 上游以 401/403 拒绝了我们的凭据（502）
+    UpstreamUnauthorized(String),
+    /// 超出限流配额，附带建议的重试等待时间
+    RateLimited(Duration),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) | AppError::PackageNotFound(_) | AppError::VersionNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            AppError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::InvalidArchive(_)
+            | AppError::UpstreamError(_)
+            | AppError::UpstreamUnauthorized(_) => StatusCode::BAD_GATEWAY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) | AppError::TooLarge(_) => StatusCode::FORBIDDEN,
+            AppError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::UpstreamUnavailable(_) | AppError::UpstreamRateLimited(..) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    /// 供客户端区分错误原因的机器可读代码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::PackageNotFound(_) => "package_not_found",
+            AppError::VersionNotFound(_) => "version_not_found",
+            AppError::InternalError(_) => "internal_error",
+            AppError::InvalidRequest(_) => "invalid_request",
+            AppError::InvalidArchive(_) => "invalid_archive",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::TooLarge(_) => "too_large",
+            AppError::UpstreamError(_) => "upstream_error",
+            AppError::UpstreamTimeout(_) => "upstream_timeout",
+            AppError::UpstreamUnavailable(_) => "upstream_unavailable",
+            AppError::UpstreamRateLimited(..) => "upstream_rate_limited",
+            AppError::UpstreamUnauthorized(_) => "upstream_unauthorized",
+            AppError::RateLimited(_) => "rate_limited",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::NotFound(msg)
+            | AppError::PackageNotFound(msg)
+            | AppError::VersionNotFound(msg)
+            | AppError::InternalError(msg)
+            | AppError::InvalidRequest(msg)
+            | AppError::InvalidArchive(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::TooLarge(msg)
+            | AppError::UpstreamError(msg)
+            | AppError::UpstreamTimeout(msg)
+            | AppError::UpstreamUnavailable(msg)
+            | AppError::UpstreamRateLimited(msg, _)
+            | AppError::UpstreamUnauthorized(msg) => msg.clone(),
+            AppError::RateLimited(_) => "Too many requests".to_string(),
+        }
+    }
 }
 
 impl fmt::Display for AppError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AppError::NotFound(msg) => write!(f, "Not Found: {}", msg),
+            AppError::PackageNotFound(msg) => write!(f, "Package Not Found: {}", msg),
+            AppError::VersionNotFound(msg) => write!(f, "Version Not Found: {}", msg),
             AppError::InternalError(msg) => write!(f, "Internal Error: {}", msg),
             AppError::InvalidRequest(msg) => write!(f, "Invalid Request: {}", msg),
+            AppError::InvalidArchive(msg) => write!(f, "Invalid Archive: {}", msg),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            AppError::TooLarge(msg) => write!(f, "Too Large: {}", msg),
+            AppError::UpstreamError(msg) => write!(f, "Upstream Error: {}", msg),
+            AppError::UpstreamTimeout(msg) => write!(f, "Upstream Timeout: {}", msg),
+            AppError::UpstreamUnavailable(msg) => write!(f, "Upstream Unavailable: {}", msg),
+            AppError::UpstreamRateLimited(msg, _) => write!(f, "Upstream Rate Limited: {}", msg),
+            AppError::UpstreamUnauthorized(msg) => write!(f, "Upstream Unauthorized: {}", msg),
+            AppError::RateLimited(retry_after) => {
+                write!(f, "Too Many Requests: retry after {:?}", retry_after)
+            }
         }
     }
 }
 
 impl std::error::Error for AppError {}
 
+/// 错误响应的代码与消息，由 [`json_errors`] 改写为 JSON 响应体
+#[derive(Debug, Clone)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-            AppError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            AppError::InvalidRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+        let status = self.status();
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.message(),
         };
 
-        tracing::error!("Error: {} - {}", status, message);
+        if status.is_server_error() {
+            tracing::error!("Error: {} - {}", status, body.message);
+        } else {
+            tracing::debug!("Error: {} - {}", status, body.message);
+        }
+
+        let mut response = (status, body.message.clone()).into_response();
+        if let AppError::RateLimited(retry_after)
+        | AppError::UpstreamRateLimited(_, Some(retry_after)) = self
+        {
+            // 向上取整，至少 1 秒
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+        }
+        response.extensions_mut().insert(body);
+        response
+    }
+}
+
+/// 客户端偏好 JSON（`Accept` 中 `application/json` 的权重不低于文本类型）时，
+/// 将错误响应改写为 `{"status", "code", "message"}`
+pub async fn json_errors(request: Request, next: Next) -> Response {
+    let wants_json = prefers_json(request.headers());
+    let mut response = next.run(request).await;
+
+    let Some(body) = response.extensions_mut().remove::<ErrorBody>() else {
+        return response;
+    };
+    if !wants_json {
+        return response;
+    }
+
+    let (mut parts, _) = response.into_parts();
+    let json = serde_json::json!({
+        "status": parts.status.as_u16(),
+        "code": body.code,
+        "message": body.message,
+    });
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, Body::from(json.to_string()))
+}
 
-        (status, message).into_response()
+pub fn prefers_json(headers: &HeaderMap) -> bool {
+    let mut json_q: f32 = 0.0;
+    let mut text_q: f32 = 0.0;
+
+    let accept = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','));
+    for item in accept {
+        let mut params = item.split(';');
+        let media = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if media == "application/json" || media.ends_with("+json") {
+            json_q = json_q.max(q);
+        } else if media.starts_with("text/") {
+            text_q = text_q.max(q);
+        }
     }
+
+    json_q > 0.0 && json_q >= text_q
 }
 
 impl From<reqwest::Error> for AppError {
     fn from(err: reqwest::Error) -> Self {
         if err.status().map(|s| s.as_u16()) == Some(404) {
             AppError::NotFound(format!("Package not found: {}", err))
+        } else if err.is_timeout() {
+            AppError::UpstreamTimeout(format!("HTTP request timed out: {}", err))
+        } else if err.is_connect() || err.is_request() || err.is_body() {
+            AppError::UpstreamError(format!("HTTP request failed: {}", err))
         } else {
             AppError::InternalError(format!("HTTP request failed: {}", err))
         }
@@ -58,3 +242,73 @@ impl From<std::io::Error> for AppError {
         AppError::InternalError(format!("IO error: {}", err))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn request(accept: Option<&str>) -> Response {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { Err::<(), _>(AppError::VersionNotFound("vue@9".to_string())) }),
+            )
+            .layer(axum::middleware::from_fn(json_errors));
+
+        let mut request = Request::get("/");
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_errors() {
+        let response = request(Some("application/json")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let json: serde_json::Value = serde_json::from_str(&body(response).await).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"status": 404, "code": "version_not_found", "message": "vue@9"})
+        );
+
+        // 浏览器与 curl 仍然得到纯文本
+        for accept in [None, Some("*/*"), Some("text/html,application/json;q=0.9")] {
+            let response = request(accept).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            assert_eq!(body(response).await, "vue@9");
+        }
+    }
+
+    #[test]
+    fn test_rate_limited_response() {
+        let response = AppError::RateLimited(Duration::from_millis(1500)).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+
+        // 上游限流时把上游的 Retry-After 转给客户端
+        let error = AppError::UpstreamRateLimited("registry".into(), Some(Duration::from_secs(30)));
+        assert_eq!(error.code(), "upstream_rate_limited");
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+        let response = AppError::UpstreamRateLimited("registry".into(), None).into_response();
+        assert!(response.headers().get(header::RETRY_AFTER).is_none());
+
+        let error = AppError::UpstreamUnauthorized("registry".into());
+        assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(error.code(), "upstream_unauthorized");
+    }
+}
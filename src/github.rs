@@ -0,0 +1,933 @@
+use crate::cache::{CacheManager, PackageData};
+use crate::error::AppError;
+use crate::package;
+use crate::source::PackageSource;
+use crate::tarball;
+use crate::upstream::UpstreamClient;
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// git 命令的超时时间
+const GIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 解析后的 git 引用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRef {
+    /// 对外展示的版本（tag 名，分支与 commit 使用完整 sha）
+    pub version: String,
+    /// 提交 sha
+    pub sha: String,
+}
+
+/// GitHub（或任意 git 服务器）仓库源
+///
+/// 引用通过 smart HTTP 的引用公告（`info/refs?service=git-upload-pack`）解析，与其他上游请求
+/// 一样经过 [`UpstreamClient`]；归档优先从 `archive_base`
+/// （codeload 风格：`{base}/{user}/{repo}/tar.gz/{sha}`）下载。
+/// `git_base` 为本地目录时对本地裸仓库执行 `git ls-remote` / `git archive`，需要安装 git。
+pub struct GitHubSource {
+    git_base: String,
+    archive_base: Option<String>,
+    client: Arc<UpstreamClient>,
+    cache: Arc<CacheManager>,
+}
+
+impl GitHubSource {
+    pub fn new(
+        git_base: impl Into<String>,
+        archive_base: Option<String>,
+        client: Arc<UpstreamClient>,
+        cache: Arc<CacheManager>,
+    ) -> Self {
+        Self {
+            git_base: git_base.into().trim_end_matches('/').to_string(),
+            archive_base: archive_base.map(|b| b.trim_end_matches('/').to_string()),
+            client,
+            cache,
+        }
+    }
+
+    /// 从环境变量读取配置
+    ///
+    /// - `GH_GIT_BASE`: git 服务器地址或本地裸仓库目录，默认 `https://github.com`
+    /// - `GH_ARCHIVE_BASE`: 归档下载地址，默认 `https://codeload.github.com`，
+    ///   设置为空字符串时使用 `git archive`
+    pub fn from_env(client: Arc<UpstreamClient>, cache: Arc<CacheManager>) -> Self {
+        let git_base =
+            std::env::var("GH_GIT_BASE").unwrap_or_else(|_| "https://github.com".to_string());
+        let archive_base = match std::env::var("GH_ARCHIVE_BASE") {
+            Ok(base) if base.trim().is_empty() => None,
+            Ok(base) => Some(base),
+            Err(_) if is_local(&git_base) => None,
+            Err(_) => Some("https://codeload.github.com".to_string()),
+        };
+        Self::new(git_base, archive_base, client, cache)
+    }
+
+    /// 获取仓库的所有引用（带缓存）
+    ///
+    /// 返回 `{"head": sha, "heads": {branch: sha}, "tags": {tag: sha}}`
+    pub async fn fetch_refs(&self, user: &str, repo: &str) -> Result<Arc<Value>, AppError> {
+        let cache_key = self.metadata_cache_key(&format!("{}/{}", user, repo));
+
+        if let Some(cached) = self.cache.get_metadata(&cache_key).await {
+            tracing::debug!("Refs cache hit for {}/{}", user, repo);
+            return Ok(cached);
+        }
+
+        tracing::debug!("Listing refs for {}/{}", user, repo);
+
+        let not_found =
+            || AppError::PackageNotFound(format!("Repository '{}/{}' not found", user, repo));
+        let remote = self.remote(user, repo);
+        let refs = if is_local(&self.git_base) {
+            let output = run_git(&["ls-remote", &remote], None)
+                .await
+                .map_err(|e| e.into_app_error(&remote, not_found))?;
+            parse_ls_remote(&String::from_utf8_lossy(&output))
+        } else {
+            let url = format!("{}/info/refs?service=git-upload-pack", remote);
+            let body = self.client.get(&url).await.map_err(|e| match e {
+                // GitHub 对不存在的仓库与私有仓库同样要求认证
+                AppError::NotFound(_) | AppError::UpstreamUnauthorized(_) => not_found(),
+                e => e,
+            })?;
+            let advertised = parse_advertisement(&body).ok_or_else(|| {
+                AppError::UpstreamError(format!("Invalid ref advertisement from {}", url))
+            })?;
+            refs_to_json(
+                advertised
+                    .iter()
+                    .map(|(sha, name)| (sha.as_str(), name.as_str())),
+            )
+        };
+        self.cache.set_metadata(cache_key, refs.clone()).await;
+
+        Ok(Arc::new(refs))
+    }
+
+    /// 解析引用，短 sha 补全为完整的提交 sha，同一提交只以一个版本缓存
+    async fn resolve(
+        &self,
+        user: &str,
+        repo: &str,
+        git_ref: Option<&str>,
+    ) -> Result<ResolvedRef, AppError> {
+        let refs = self.fetch_refs(user, repo).await?;
+        let resolved = resolve_ref(&refs, git_ref)?;
+        if resolved.sha.len() == 40 {
+            return Ok(resolved);
+        }
+        let sha = self.expand_sha(user, repo, &resolved.sha).await?;
+        Ok(ResolvedRef {
+            version: sha.clone(),
+            sha,
+        })
+    }
+
+    /// 查询短 sha 对应的完整提交 sha（带缓存）
+    ///
+    /// 远程仓库没有不下载对象就能查询提交的接口，因此直接下载归档：`git archive` 生成的归档
+    /// 记录了完整的提交 sha，下载的文件同时按完整 sha 写入包缓存。
+    async fn expand_sha(&self, user: &str, repo: &str, short: &str) -> Result<String, AppError> {
+        let name = format!("{}/{}", user, repo);
+        let cache_key = format!("{}#{}", self.metadata_cache_key(&name), short);
+        if let Some(sha) = self.cache.get_metadata(&cache_key).await {
+            if let Some(sha) = sha.as_str() {
+                return Ok(sha.to_string());
+            }
+        }
+
+        let not_found = || {
+            AppError::VersionNotFound(format!("Commit '{}' not found in {}/{}", short, user, repo))
+        };
+        let sha = if self.archive_base.is_none() {
+            let remote = self.remote(user, repo);
+            let object = format!("{}^{{commit}}", short);
+            let output = run_git(
+                &["rev-parse", "--verify", "--quiet", &object],
+                Some(&remote),
+            )
+            .await
+            .map_err(|e| e.into_app_error(&remote, not_found))?;
+            String::from_utf8_lossy(&output).trim().to_string()
+        } else {
+            let archive = self.fetch_archive(user, repo, short).await?;
+            let sha = tarball::commit_id(&archive)
+                .filter(|sha| sha.len() == 40 && sha.starts_with(short))
+                .ok_or_else(|| {
+                    AppError::UpstreamError(format!(
+                        "Archive of {}@{} does not record its commit",
+                        name, short
+                    ))
+                })?;
+            let package_data = index_archive(archive).await?;
+            self.cache
+                .set_package(self.package_cache_key(&name, &sha), Arc::new(package_data))
+                .await;
+            sha
+        };
+
+        self.cache.set_metadata(cache_key, json!(sha)).await;
+        Ok(sha)
+    }
+
+    /// 下载并解压指定提交的仓库文件
+    pub async fn fetch_repo(
+        &self,
+        user: &str,
+        repo: &str,
+        sha: &str,
+    ) -> Result<PackageData, AppError> {
+        index_archive(self.fetch_archive(user, repo, sha).await?).await
+    }
+
+    /// 下载指定提交的 gzip 归档
+    async fn fetch_archive(&self, user: &str, repo: &str, sha: &str) -> Result<Bytes, AppError> {
+        let not_found = || {
+            AppError::VersionNotFound(format!("Commit '{}' not found in {}/{}", sha, user, repo))
+        };
+        match &self.archive_base {
+            Some(base) => {
+                let url = format!("{}/{}/{}/tar.gz/{}", base, user, repo, sha);
+                tracing::debug!("Downloading repository archive from {}", url);
+
+                self.client.get(&url).await.map_err(|e| match e {
+                    AppError::NotFound(_) => not_found(),
+                    e => e,
+                })
+            }
+            None => {
+                let remote = self.remote(user, repo);
+                let prefix = format!("--prefix={}/", repo);
+                run_git(&["archive", "--format=tar.gz", &prefix, sha], Some(&remote))
+                    .await
+                    .map(Bytes::from)
+                    .map_err(|e| e.into_app_error(&remote, not_found))
+            }
+        }
+    }
+
+    /// 仓库地址：本地目录优先使用 `{repo}.git`
+    fn remote(&self, user: &str, repo: &str) -> String {
+        if is_local(&self.git_base) {
+            let base = PathBuf::from(self.git_base.trim_start_matches("file://"));
+            let bare = base.join(user).join(format!("{}.git", repo));
+            if bare.exists() {
+                return bare.to_string_lossy().into_owned();
+            }
+            return base.join(user).join(repo).to_string_lossy().into_owned();
+        }
+        format!("{}/{}/{}", self.git_base, user, repo)
+    }
+}
+
+/// 为仓库归档建立索引（在阻塞线程中执行）
+async fn index_archive(archive: Bytes) -> Result<PackageData, AppError> {
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        span.in_scope(|| {
+            let files = tarball::Index::build(archive)?;
+            // 仓库不一定有 package.json
+            let package_json = match files.read("package.json")? {
+                Some(content) => serde_json::from_slice(&content).unwrap_or_else(|_| json!({})),
+                None => json!({}),
+            };
+            Ok(PackageData {
+                files,
+                package_json,
+            })
+        })
+    })
+    .await
+    .map_err(|e| AppError::InternalError(format!("Extraction task failed: {}", e)))?
+}
+
+#[async_trait]
+impl PackageSource for GitHubSource {
+    /// 包名为 `{user}/{repo}`
+    fn parse_path(&self, path: &str) -> Result<(String, Option<String>, Option<String>), AppError> {
+        let (user, repo, git_ref, file_path) = parse_path(path)?;
+        Ok((format!("{}/{}", user, repo), git_ref, file_path))
+    }
+
+    async fn resolve_version(&self, name: &str, spec: Option<&str>) -> Result<String, AppError> {
+        let (user, repo) = split_name(name)?;
+        let resolved = self.resolve(user, repo, spec).await?;
+
+        tracing::debug!(
+            "Resolved {}@{:?} to {} ({})",
+            name,
+            spec,
+            resolved.version,
+            resolved.sha
+        );
+
+        Ok(resolved.version)
+    }
+
+    /// 列出语义化版本 tag
+    async fn list_versions(&self, name: &str) -> Result<Vec<String>, AppError> {
+        let (user, repo) = split_name(name)?;
+        let refs = self.fetch_refs(user, repo).await?;
+
+        let mut tags: Vec<(node_semver::Version, String)> = refs
+            .get("tags")
+            .and_then(|v| v.as_object())
+            .map(|tags| {
+                tags.keys()
+                    .filter_map(|tag| {
+                        node_semver::Version::parse(tag.trim_start_matches('v'))
+                            .ok()
+                            .map(|v| (v, tag.clone()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        tags.sort();
+        Ok(tags.into_iter().map(|(_, tag)| tag).collect())
+    }
+
+    /// `version` 是 tag 名或提交 sha，短 sha 按完整 sha 缓存
+    async fn fetch_package(&self, name: &str, version: &str) -> Result<Arc<PackageData>, AppError> {
+        if let Some(cached) = self
+            .cache
+            .get_package(&self.package_cache_key(name, version))
+            .await
+        {
+            tracing::debug!("Repository cache hit for {}@{}", name, version);
+            return Ok(cached);
+        }
+
+        let (user, repo) = split_name(name)?;
+        let resolved = self.resolve(user, repo, Some(version)).await?;
+        let cache_key = self.package_cache_key(name, &resolved.version);
+        if let Some(cached) = self.cache.get_package(&cache_key).await {
+            return Ok(cached);
+        }
+        let package_data = self.fetch_repo(user, repo, &resolved.sha).await?;
+
+        let package_data = Arc::new(package_data);
+        self.cache
+            .set_package(cache_key, package_data.clone())
+            .await;
+
+        Ok(package_data)
+    }
+
+    fn metadata_cache_key(&self, name: &str) -> String {
+        format!("metadata:gh/{}", name)
+    }
+
+    fn package_cache_key(&self, name: &str, version: &str) -> String {
+        format!("package:gh/{}@{}", name, version)
+    }
+}
+
+fn split_name(name: &str) -> Result<(&str, &str), AppError> {
+    name.split_once('/')
+        .ok_or_else(|| AppError::InvalidRequest(format!("Invalid repository: {}", name)))
+}
+
+/// 解析 `/gh/` 之后未解码的路径为 (用户, 仓库, 引用, 文件路径)，引用与文件路径逐段解码
+pub fn parse_path(
+    path: &str,
+) -> Result<(String, String, Option<String>, Option<String>), AppError> {
+    let path = path.trim_start_matches('/');
+
+    let (user, rest) = path
+        .split_once('/')
+        .ok_or_else(|| AppError::InvalidRequest("Expected /gh/{user}/{repo}".to_string()))?;
+
+    let (repo_part, file_path) = match rest.split_once('/') {
+        Some((repo_part, file_path)) => (repo_part, Some(package::decode_file_path(file_path)?)),
+        None => (rest, None),
+    };
+
+    let (repo, git_ref) = match repo_part.split_once('@') {
+        Some((repo, git_ref)) => (repo, Some(package::decode_segment(git_ref)?)),
+        None => (repo_part, None),
+    };
+
+    if !is_valid_name(user) || !is_valid_name(repo) {
+        return Err(AppError::InvalidRequest(format!(
+            "Invalid repository name: {}/{}",
+            user, repo
+        )));
+    }
+
+    if let Some(ref r) = git_ref {
+        if r.is_empty() || r.starts_with('-') || r.chars().any(|c| c.is_whitespace()) {
+            return Err(AppError::InvalidRequest(format!("Invalid git ref: {}", r)));
+        }
+    }
+
+    Ok((user.to_string(), repo.to_string(), git_ref, file_path))
+}
+
+/// 解析引用：未指定时使用最高的语义化版本 tag（没有 tag 时使用默认分支），
+/// 否则依次尝试分支、tag、语义化版本范围（匹配 tag）和 commit sha。
+pub fn resolve_ref(refs: &Value, git_ref: Option<&str>) -> Result<ResolvedRef, AppError> {
+    let heads = refs.get("heads").and_then(|v| v.as_object());
+    let tags = refs.get("tags").and_then(|v| v.as_object());
+
+    let Some(git_ref) = git_ref else {
+        if let Some(resolved) = tags.and_then(|tags| highest_tag(tags, None)) {
+            return Ok(resolved);
+        }
+        return refs
+            .get("head")
+            .and_then(|v| v.as_str())
+            .map(|sha| ResolvedRef {
+                version: sha.to_string(),
+                sha: sha.to_string(),
+            })
+            .ok_or_else(|| {
+                AppError::VersionNotFound("Repository has no default branch".to_string())
+            });
+    };
+
+    if let Some(sha) = heads.and_then(|h| h.get(git_ref)).and_then(|v| v.as_str()) {
+        return Ok(ResolvedRef {
+            version: sha.to_string(),
+            sha: sha.to_string(),
+        });
+    }
+
+    if let Some(sha) = tags.and_then(|t| t.get(git_ref)).and_then(|v| v.as_str()) {
+        return Ok(ResolvedRef {
+            version: git_ref.to_string(),
+            sha: sha.to_string(),
+        });
+    }
+
+    if let Ok(range) = node_semver::Range::parse(git_ref) {
+        if let Some(resolved) = tags.and_then(|tags| highest_tag(tags, Some(&range))) {
+            return Ok(resolved);
+        }
+    }
+
+    if (7..=40).contains(&git_ref.len()) && git_ref.chars().all(|c| c.is_ascii_hexdigit()) {
+        // 短 sha 唯一匹配某个引用指向的提交时直接补全，否则由调用方查询仓库
+        let prefix = git_ref.to_lowercase();
+        let mut matches: Vec<&str> = refs
+            .get("head")
+            .into_iter()
+            .chain(heads.into_iter().flat_map(|h| h.values()))
+            .chain(tags.into_iter().flat_map(|t| t.values()))
+            .filter_map(|v| v.as_str())
+            .filter(|sha| sha.starts_with(&prefix))
+            .collect();
+        matches.sort_unstable();
+        matches.dedup();
+        let sha = match matches[..] {
+            [sha] => sha.to_string(),
+            _ => prefix,
+        };
+        return Ok(ResolvedRef {
+            version: sha.clone(),
+            sha,
+        });
+    }
+
+    Err(AppError::VersionNotFound(format!(
+        "No branch, tag or commit matches '{}'",
+        git_ref
+    )))
+}
+
+/// 在 tag 中找出满足范围的最高语义化版本（允许 `v` 前缀）
+fn highest_tag(
+    tags: &Map<String, Value>,
+    range: Option<&node_semver::Range>,
+) -> Option<ResolvedRef> {
+    tags.iter()
+        .filter_map(|(tag, sha)| {
+            let version = node_semver::Version::parse(tag.trim_start_matches('v')).ok()?;
+            Some((version, tag, sha.as_str()?))
+        })
+        .filter(|(version, _, _)| range.map(|r| r.satisfies(version)).unwrap_or(true))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, tag, sha)| ResolvedRef {
+            version: tag.clone(),
+            sha: sha.to_string(),
+        })
+}
+
+/// 解析 `git ls-remote` 的输出
+fn parse_ls_remote(output: &str) -> Value {
+    refs_to_json(output.lines().filter_map(|line| line.split_once('\t')))
+}
+
+/// 解析 smart HTTP 的引用公告（pkt-line 格式），返回 (sha, 引用名)；格式不符时返回 None
+///
+/// 第一行为 `# service=git-upload-pack`，第一个引用后以 NUL 分隔附带服务端能力，
+/// 空仓库只有一个名为 `capabilities^{}` 的占位引用。
+fn parse_advertisement(body: &[u8]) -> Option<Vec<(String, String)>> {
+    let mut lines = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        let len = std::str::from_utf8(rest.get(..4)?).ok()?;
+        let len = usize::from_str_radix(len, 16).ok()?;
+        if len == 0 {
+            // flush-pkt
+            rest = &rest[4..];
+            continue;
+        }
+        if len < 4 {
+            return None;
+        }
+        let line = String::from_utf8_lossy(rest.get(4..len)?);
+        lines.push(line.trim_end_matches('\n').to_string());
+        rest = &rest[len..];
+    }
+
+    let mut lines = lines.into_iter();
+    if lines.next()? != "# service=git-upload-pack" {
+        return None;
+    }
+    let mut refs = Vec::new();
+    for line in lines {
+        let line = line.split('\0').next().unwrap_or_default();
+        let (sha, name) = line.split_once(' ')?;
+        if sha.len() != 40 || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        if name != "capabilities^{}" {
+            refs.push((sha.to_string(), name.to_string()));
+        }
+    }
+    Some(refs)
+}
+
+/// 将 (sha, 引用名) 整理为 `{"head", "heads", "tags"}`，附注 tag 使用 `^{}` 解引用后的提交
+fn refs_to_json<'a>(refs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Value {
+    let mut head = Value::Null;
+    let mut heads = Map::new();
+    let mut tags = Map::new();
+
+    for (sha, name) in refs {
+        let sha = Value::String(sha.trim().to_string());
+
+        if name == "HEAD" {
+            head = sha;
+        } else if let Some(branch) = name.strip_prefix("refs/heads/") {
+            heads.insert(branch.to_string(), sha);
+        } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+            match tag.strip_suffix("^{}") {
+                Some(tag) => {
+                    tags.insert(tag.to_string(), sha);
+                }
+                None => {
+                    tags.entry(tag.to_string()).or_insert(sha);
+                }
+            }
+        }
+    }
+
+    json!({
+        "head": head,
+        "heads": heads,
+        "tags": tags,
+    })
+}
+
+/// GitHub 用户名与仓库名允许的字符
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn is_local(base: &str) -> bool {
+    base.starts_with("file://") || !base.contains("://")
+}
+
+/// git 命令失败的原因
+#[derive(Debug)]
+enum GitError {
+    /// 超过 [`GIT_TIMEOUT`]
+    Timeout,
+    /// 无法启动 git（例如没有安装）
+    Spawn(std::io::Error),
+    /// 命令返回非零状态，带有标准错误输出
+    Failed(String),
+}
+
+impl GitError {
+    /// 命令本身失败（仓库或提交不存在）时返回 `not_found`，无法运行 git 时返回上游错误
+    fn into_app_error(self, remote: &str, not_found: impl FnOnce() -> AppError) -> AppError {
+        match self {
+            GitError::Timeout => {
+                AppError::UpstreamTimeout(format!("git command on {} timed out", remote))
+            }
+            GitError::Spawn(e) => {
+                AppError::UpstreamUnavailable(format!("Failed to run git: {}", e))
+            }
+            GitError::Failed(stderr) => {
+                tracing::debug!("git command on {} failed: {}", remote, stderr);
+                not_found()
+            }
+        }
+    }
+}
+
+/// 执行 git 命令并返回标准输出，`git_dir` 用于指定本地仓库
+async fn run_git(args: &[&str], git_dir: Option<&str>) -> Result<Vec<u8>, GitError> {
+    let mut command = Command::new("git");
+    if let Some(git_dir) = git_dir {
+        command.arg("--git-dir").arg(git_dir);
+    }
+    command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let output = tokio::time::timeout(GIT_TIMEOUT, command.output())
+        .await
+        .map_err(|_| GitError::Timeout)?
+        .map_err(GitError::Spawn)?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(GitError::Failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &std::path::Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(["-c", "init.defaultBranch=main"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    /// 在临时目录中创建 `{base}/octo/assets.git` 裸仓库，返回 (目录, main 的提交, 没有引用指向的提交)
+    fn fixture() -> (tempfile::TempDir, String, String) {
+        let base = tempfile::tempdir().unwrap();
+        let work = base.path().join("work");
+        std::fs::create_dir_all(work.join("dist")).unwrap();
+        git(&work, &["init", "-q"]);
+
+        std::fs::write(work.join("package.json"), r#"{"main": "dist/a.js"}"#).unwrap();
+        std::fs::write(work.join("dist/a.js"), "v1").unwrap();
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "-qm", "v1"]);
+        git(&work, &["tag", "v1.0.0"]);
+
+        std::fs::write(work.join("dist/a.js"), "v2").unwrap();
+        git(&work, &["commit", "-qam", "v2"]);
+        git(&work, &["tag", "-a", "v1.1.0", "-m", "release"]);
+
+        std::fs::write(work.join("dist/a.js"), "wip").unwrap();
+        git(&work, &["commit", "-qam", "wip"]);
+        let wip = git(&work, &["rev-parse", "HEAD"]);
+
+        std::fs::write(work.join("dist/a.js"), "main").unwrap();
+        git(&work, &["commit", "-qam", "main"]);
+        let head = git(&work, &["rev-parse", "HEAD"]);
+
+        std::fs::create_dir_all(base.path().join("octo")).unwrap();
+        git(
+            base.path(),
+            &["clone", "-q", "--bare", "work", "octo/assets.git"],
+        );
+
+        (base, head, wip)
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let (user, repo, git_ref, file) = parse_path("octo/assets@v1.0.0/dist/a.js").unwrap();
+        assert_eq!((user.as_str(), repo.as_str()), ("octo", "assets"));
+        assert_eq!(git_ref.as_deref(), Some("v1.0.0"));
+        assert_eq!(file.as_deref(), Some("dist/a.js"));
+
+        let (_, repo, git_ref, file) = parse_path("octo/assets").unwrap();
+        assert_eq!(repo, "assets");
+        assert_eq!((git_ref, file), (None, None));
+
+        assert!(parse_path("octo").is_err());
+        assert!(parse_path("octo/assets@--upload-pack=x").is_err());
+        assert!(parse_path("../etc/x").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_bare_repository() {
+        let (base, head, wip) = fixture();
+        let source = GitHubSource::new(
+            base.path().to_string_lossy(),
+            None,
+            Arc::new(UpstreamClient::new(
+                reqwest::Client::new(),
+                Default::default(),
+            )),
+            Arc::new(CacheManager::new()),
+        );
+
+        // 未指定引用时使用最高的 tag
+        let latest = source.resolve_version("octo/assets", None).await.unwrap();
+        assert_eq!(latest, "v1.1.0");
+
+        // 语义化版本范围匹配 tag
+        assert_eq!(
+            source
+                .resolve_version("octo/assets", Some("~1.0"))
+                .await
+                .unwrap(),
+            "v1.0.0"
+        );
+
+        // 分支解析为提交 sha
+        let main = source
+            .resolve_version("octo/assets", Some("main"))
+            .await
+            .unwrap();
+        assert_eq!(main, head);
+
+        assert_eq!(
+            source.list_versions("octo/assets").await.unwrap(),
+            vec!["v1.0.0", "v1.1.0"]
+        );
+
+        let data = source.fetch_package("octo/assets", &latest).await.unwrap();
+        assert_eq!(data.files.read("dist/a.js").unwrap().unwrap(), &b"v2"[..]);
+        assert_eq!(data.package_json["main"], "dist/a.js");
+
+        let data = source
+            .fetch_package("octo/assets", &head[..10])
+            .await
+            .unwrap();
+        assert_eq!(data.files.read("dist/a.js").unwrap().unwrap(), &b"main"[..]);
+
+        // 短 sha 补全为完整 sha，同一提交只缓存一份
+        assert_eq!(
+            source
+                .resolve_version("octo/assets", Some(&head[..10]))
+                .await
+                .unwrap(),
+            head
+        );
+        assert_eq!(
+            source
+                .resolve_version("octo/assets", Some(&wip[..7]))
+                .await
+                .unwrap(),
+            wip
+        );
+        let data = source
+            .fetch_package("octo/assets", &wip[..7])
+            .await
+            .unwrap();
+        assert_eq!(data.files.read("dist/a.js").unwrap().unwrap(), &b"wip"[..]);
+        assert!(source
+            .cache
+            .contains_package(&source.package_cache_key("octo/assets", &wip)));
+        assert!(!source
+            .cache
+            .contains_package(&source.package_cache_key("octo/assets", &wip[..7])));
+        assert!(matches!(
+            source.resolve_version("octo/assets", Some("0000000")).await,
+            Err(AppError::VersionNotFound(_))
+        ));
+
+        assert!(matches!(
+            source.resolve_version("octo/missing", None).await,
+            Err(AppError::PackageNotFound(_))
+        ));
+        assert!(source
+            .resolve_version("octo/assets", Some("no-such-branch"))
+            .await
+            .is_err());
+    }
+
+    /// 模拟 GitHub：`info/refs` 返回裸仓库的引用公告，`tar.gz/{sha}` 返回 `git archive` 的结果，
+    /// `octo/down` 返回 503，其他仓库与 GitHub 一样返回 401
+    async fn fake_github(bare: std::path::PathBuf) -> String {
+        use axum::extract::{Path as UrlPath, State};
+        use axum::http::StatusCode;
+        use axum::routing::get;
+
+        let app =
+            axum::Router::new()
+                .route(
+                    "/:user/:repo/info/refs",
+                    get(
+                        |State(bare): State<Arc<std::path::PathBuf>>,
+                         UrlPath((user, repo)): UrlPath<(String, String)>| async move {
+                            match (user.as_str(), repo.as_str()) {
+                                ("octo", "assets") => {}
+                                ("octo", "down") => return Err(StatusCode::SERVICE_UNAVAILABLE),
+                                _ => return Err(StatusCode::UNAUTHORIZED),
+                            }
+                            let output = std::process::Command::new("git")
+                                .args(["upload-pack", "--stateless-rpc", "--advertise-refs"])
+                                .arg(bare.as_path())
+                                .output()
+                                .unwrap();
+                            let mut body = b"001e# service=git-upload-pack\n0000".to_vec();
+                            body.extend(output.stdout);
+                            Ok(body)
+                        },
+                    ),
+                )
+                .route(
+                    "/octo/assets/tar.gz/:sha",
+                    get(
+                        |State(bare): State<Arc<std::path::PathBuf>>,
+                         UrlPath(sha): UrlPath<String>| async move {
+                            let output = std::process::Command::new("git")
+                                .arg("--git-dir")
+                                .arg(bare.as_path())
+                                .args(["archive", "--format=tar.gz", "--prefix=assets/", &sha])
+                                .output()
+                                .unwrap();
+                            if output.status.success() {
+                                Ok(output.stdout)
+                            } else {
+                                Err(StatusCode::NOT_FOUND)
+                            }
+                        },
+                    ),
+                )
+                .with_state(Arc::new(bare));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_refs_over_http() {
+        let (base, head, wip) = fixture();
+        let server = fake_github(base.path().join("octo/assets.git")).await;
+        let config = crate::upstream::UpstreamConfig {
+            retries: 0,
+            ..Default::default()
+        };
+        let source = GitHubSource::new(
+            server.clone(),
+            Some(server),
+            Arc::new(UpstreamClient::new(reqwest::Client::new(), config)),
+            Arc::new(CacheManager::new()),
+        );
+
+        assert_eq!(
+            source.resolve_version("octo/assets", None).await.unwrap(),
+            "v1.1.0"
+        );
+        assert_eq!(
+            source
+                .resolve_version("octo/assets", Some("main"))
+                .await
+                .unwrap(),
+            head
+        );
+
+        // 没有引用指向的短 sha：从归档中读出完整 sha，文件按完整 sha 缓存
+        assert_eq!(
+            source
+                .resolve_version("octo/assets", Some(&wip[..8]))
+                .await
+                .unwrap(),
+            wip
+        );
+        let key = source.package_cache_key("octo/assets", &wip);
+        let cached = source.cache.get_package(&key).await.unwrap();
+        let data = source
+            .fetch_package("octo/assets", &wip[..8])
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&data, &cached));
+        assert_eq!(data.files.read("dist/a.js").unwrap().unwrap(), &b"wip"[..]);
+
+        assert!(matches!(
+            source.resolve_version("octo/missing", None).await,
+            Err(AppError::PackageNotFound(_))
+        ));
+        // 上游故障不是"仓库不存在"
+        let down = source.resolve_version("octo/down", None).await.unwrap_err();
+        assert!(down.status().is_server_error(), "{:?}", down);
+    }
+
+    #[test]
+    fn test_parse_advertisement() {
+        let pkt = |line: String| format!("{:04x}{}", line.len() + 4, line);
+        let sha = "a".repeat(40);
+        let body = [
+            pkt("# service=git-upload-pack\n".to_string()),
+            "0000".to_string(),
+            pkt(format!(
+                "{} HEAD\0multi_ack symref=HEAD:refs/heads/main\n",
+                sha
+            )),
+            pkt(format!("{} refs/heads/main\n", sha)),
+            "0000".to_string(),
+        ]
+        .concat();
+        assert_eq!(
+            parse_advertisement(body.as_bytes()).unwrap(),
+            vec![
+                (sha.clone(), "HEAD".to_string()),
+                (sha.clone(), "refs/heads/main".to_string())
+            ]
+        );
+
+        // 空仓库
+        let empty = [
+            pkt("# service=git-upload-pack\n".to_string()),
+            "0000".to_string(),
+            pkt(format!("{} capabilities^{{}}\0agent=git\n", "0".repeat(40))),
+            "0000".to_string(),
+        ]
+        .concat();
+        assert_eq!(parse_advertisement(empty.as_bytes()).unwrap(), vec![]);
+
+        assert!(parse_advertisement(b"<html>Not a git server</html>").is_none());
+        assert!(parse_advertisement(b"0010short").is_none());
+    }
+
+    #[test]
+    fn test_git_errors() {
+        let not_found = || AppError::PackageNotFound("octo/assets".to_string());
+        assert!(matches!(
+            GitError::Timeout.into_app_error("r", not_found),
+            AppError::UpstreamTimeout(_)
+        ));
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(matches!(
+            GitError::Spawn(missing).into_app_error("r", not_found),
+            AppError::UpstreamUnavailable(_)
+        ));
+        assert!(matches!(
+            GitError::Failed("fatal: not a git repository".to_string())
+                .into_app_error("r", not_found),
+            AppError::PackageNotFound(_)
+        ));
+    }
+}
@@ -0,0 +1,447 @@
+use crate::error::AppError;
+use axum::{
+    http::{header, HeaderName, HeaderValue, Method},
+    response::Response,
+    Router,
+};
+use std::time::Duration;
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    set_header::SetResponseHeaderLayer,
+};
+
+static CROSS_ORIGIN_RESOURCE_POLICY: HeaderName =
+    HeaderName::from_static("cross-origin-resource-policy");
+static TIMING_ALLOW_ORIGIN: HeaderName = HeaderName::from_static("timing-allow-origin");
+
+/// 允许跨域脚本读取的响应头
+const EXPOSED_HEADERS: &[&str] = &[
+    "content-length",
+    "content-encoding",
+    "sourcemap",
+    "x-cache",
+    "x-served-version",
+    "x-request-id",
+    "x-integrity",
+];
+
+/// 跨域与安全响应头策略
+///
+/// 默认值面向公共 CDN：允许任意来源读取、允许跨域嵌入、开放 Resource Timing，
+/// 并禁止浏览器嗅探 Content-Type。
+#[derive(Debug, Clone)]
+pub struct HeaderPolicy {
+    /// 允许的来源，`None` 表示任意来源（`*`），空列表表示不处理跨域（不发送 CORS 响应头）
+    pub allow_origins: Option<Vec<HeaderValue>>,
+    /// 预检请求结果的缓存时间
+    pub max_age: Duration,
+    /// `Cross-Origin-Resource-Policy` 的值，`None` 表示不发送
+    pub cross_origin_resource_policy: Option<HeaderValue>,
+    /// `Timing-Allow-Origin` 的值，`None` 表示不发送
+    pub timing_allow_origin: Option<HeaderValue>,
+    /// 是否发送 `X-Content-Type-Options: nosniff`
+    pub nosniff: bool,
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        Self {
+            allow_origins: None,
+            max_age: Duration::from_secs(86400),
+            cross_origin_resource_policy: Some(HeaderValue::from_static("cross-origin")),
+            timing_allow_origin: Some(HeaderValue::from_static("*")),
+            nosniff: true,
+        }
+    }
+}
+
+impl HeaderPolicy {
+    /// 从环境变量读取策略，未设置的项使用默认值，设置为空字符串表示关闭该响应头
+    ///
+    /// - `CORS_ALLOW_ORIGINS`: `*` 或逗号分隔的来源列表（来源全部无效时启动失败）
+    /// - `CORS_MAX_AGE`: 预检缓存秒数
+    /// - `CROSS_ORIGIN_RESOURCE_POLICY`: `cross-origin` / `same-site` / `same-origin`
+    /// - `TIMING_ALLOW_ORIGIN`: `*` 或来源列表
+    /// - `CONTENT_TYPE_NOSNIFF`: `true` / `false`
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+
+        if let Ok(origins) = std::env::var("CORS_ALLOW_ORIGINS") {
+            policy.allow_origins = parse_origins(&origins);
+        }
+        if let Some(secs) = std::env::var("CORS_MAX_AGE")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            policy.max_age = Duration::from_secs(secs);
+        }
+        if let Ok(value) = std::env::var("CROSS_ORIGIN_RESOURCE_POLICY") {
+            policy.cross_origin_resource_policy = optional_header_value(&value);
+        }
+        if let Ok(value) = std::env::var("TIMING_ALLOW_ORIGIN") {
+            policy.timing_allow_origin = optional_header_value(&value);
+        }
+        if let Ok(value) = std::env::var("CONTENT_TYPE_NOSNIFF") {
+            policy.nosniff = !matches!(value.trim(), "false" | "0" | "off");
+        }
+
+        policy
+    }
+
+    /// 将策略应用到整个路由（包括 CORS 预检请求的处理）
+    pub fn apply(&self, router: Router) -> Router {
+        let mut router = router;
+
+        if self.nosniff {
+            router = router.layer(SetResponseHeaderLayer::overriding(
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ));
+        }
+        if let Some(value) = &self.timing_allow_origin {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(
+                TIMING_ALLOW_ORIGIN.clone(),
+                value.clone(),
+            ));
+        }
+        if let Some(value) = &self.cross_origin_resource_policy {
+            router = router.layer(SetResponseHeaderLayer::if_not_present(
+                CROSS_ORIGIN_RESOURCE_POLICY.clone(),
+                value.clone(),
+            ));
+        }
+
+        if self.allow_origins.as_ref().is_some_and(Vec::is_empty) {
+            return router;
+        }
+        router.layer(self.cors_layer())
+    }
+
+    fn cors_layer(&self) -> CorsLayer {
+        let allow_origin = match &self.allow_origins {
+            None => AllowOrigin::any(),
+            Some(origins) => AllowOrigin::list(origins.iter().cloned()),
+        };
+
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::HEAD, Method::OPTIONS])
+            .allow_headers([
+                header::RANGE,
+                header::IF_NONE_MATCH,
+                header::IF_MODIFIED_SINCE,
+            ])
+            .expose_headers(
+                EXPOSED_HEADERS
+                    .iter()
+                    .map(|name| HeaderName::from_static(name))
+                    .collect::<Vec<_>>(),
+            )
+            .max_age(self.max_age)
+    }
+}
+
+/// 解析来源列表，`*` 表示任意来源，空字符串表示关闭 CORS
+///
+/// 配置了来源但全部无效时直接失败，避免在运维人员不知情的情况下拒绝所有跨域请求。
+fn parse_origins(value: &str) -> Option<Vec<HeaderValue>> {
+    let origins: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    if origins.contains(&"*") {
+        return None;
+    }
+    if origins.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let valid: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin: {}", origin);
+                None
+            }
+        })
+        .collect();
+    if valid.is_empty() {
+        panic!("CORS_ALLOW_ORIGINS contains no valid origin: {}", value);
+    }
+    Some(valid)
+}
+
+fn optional_header_value(value: &str) -> Option<HeaderValue> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        HeaderValue::from_str(value).ok()
+    }
+}
+
+/// 包内可执行内容（HTML、SVG、XML）的处理策略
+///
+/// 任何 npm 包作者都可以发布这类文件，直接以原始类型从 CDN 域名返回
+/// 会让其中的脚本在我们的源下执行。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActiveContentPolicy {
+    /// 保留原始 Content-Type，附加 `Content-Security-Policy: sandbox`
+    Sandbox,
+    /// 以 `text/plain` 返回
+    PlainText,
+    /// 拒绝返回（403）
+    Block,
+    /// 不做处理
+    Allow,
+}
+
+impl ActiveContentPolicy {
+    /// 从 `ACTIVE_CONTENT_POLICY` 环境变量读取（`sandbox` / `plain` / `block` / `allow`），默认 `sandbox`
+    pub fn from_env() -> Self {
+        match std::env::var("ACTIVE_CONTENT_POLICY") {
+            Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+                "plain" | "text" => ActiveContentPolicy::PlainText,
+                "block" | "deny" => ActiveContentPolicy::Block,
+                "allow" | "none" => ActiveContentPolicy::Allow,
+                "sandbox" | "" => ActiveContentPolicy::Sandbox,
+                other => {
+                    tracing::warn!("Unknown ACTIVE_CONTENT_POLICY '{}', using sandbox", other);
+                    ActiveContentPolicy::Sandbox
+                }
+            },
+            Err(_) => ActiveContentPolicy::Sandbox,
+        }
+    }
+
+    /// 对包内文件的响应应用策略
+    pub fn apply(&self, response: &mut Response) -> Result<(), AppError> {
+        let headers = response.headers_mut();
+        let is_active = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(is_active_content_type)
+            .unwrap_or(false);
+
+        if !is_active {
+            return Ok(());
+        }
+
+        match self {
+            ActiveContentPolicy::Sandbox => {
+                headers.insert(
+                    header::CONTENT_SECURITY_POLICY,
+                    HeaderValue::from_static("sandbox"),
+                );
+            }
+            ActiveContentPolicy::PlainText => {
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; charset=utf-8"),
+                );
+            }
+            ActiveContentPolicy::Block => {
+                return Err(AppError::Forbidden(
+                    "HTML, SVG and XML files are not served".to_string(),
+                ));
+            }
+            ActiveContentPolicy::Allow => {}
+        }
+        Ok(())
+    }
+}
+
+/// 浏览器会作为文档渲染并可能执行脚本的类型
+fn is_active_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    matches!(
+        mime,
+        "text/html" | "application/xhtml+xml" | "image/svg+xml" | "application/xml" | "text/xml"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get};
+    use tower::ServiceExt;
+
+    fn app(policy: &HeaderPolicy) -> Router {
+        policy.apply(Router::new().route("/*path", get(|| async { "ok" })))
+    }
+
+    #[tokio::test]
+    async fn test_default_headers() {
+        let response = app(&HeaderPolicy::default())
+            .oneshot(
+                Request::get("/vue/index.js")
+                    .header(header::ORIGIN, "https://example.com")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers["cross-origin-resource-policy"], "cross-origin");
+        assert_eq!(headers["timing-allow-origin"], "*");
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let response = app(&HeaderPolicy::default())
+            .oneshot(
+                Request::options("/vue/index.js")
+                    .header(header::ORIGIN, "https://example.com")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap()
+            .contains("GET"));
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "86400");
+    }
+
+    #[tokio::test]
+    async fn test_origin_allow_list() {
+        let policy = HeaderPolicy {
+            allow_origins: parse_origins("https://a.example, https://b.example"),
+            ..HeaderPolicy::default()
+        };
+
+        let allowed = app(&policy)
+            .oneshot(
+                Request::get("/x")
+                    .header(header::ORIGIN, "https://b.example")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            allowed.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://b.example"
+        );
+
+        let denied = app(&policy)
+            .oneshot(
+                Request::get("/x")
+                    .header(header::ORIGIN, "https://evil.example")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(denied
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[test]
+    fn test_active_content_policy() {
+        use axum::response::IntoResponse;
+
+        let svg = || ([(header::CONTENT_TYPE, "image/svg+xml")], "<svg/>").into_response();
+        let js = || {
+            (
+                [(
+                    header::CONTENT_TYPE,
+                    "application/javascript; charset=utf-8",
+                )],
+                "1",
+            )
+                .into_response()
+        };
+
+        let mut response = svg();
+        ActiveContentPolicy::Sandbox.apply(&mut response).unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_SECURITY_POLICY],
+            "sandbox"
+        );
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/svg+xml");
+
+        let mut response = svg();
+        ActiveContentPolicy::PlainText.apply(&mut response).unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+
+        let mut response = svg();
+        assert!(matches!(
+            ActiveContentPolicy::Block.apply(&mut response),
+            Err(AppError::Forbidden(_))
+        ));
+
+        let mut response = js();
+        ActiveContentPolicy::Sandbox.apply(&mut response).unwrap();
+        assert!(response
+            .headers()
+            .get(header::CONTENT_SECURITY_POLICY)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cors_disabled() {
+        let policy = HeaderPolicy {
+            allow_origins: parse_origins(" "),
+            ..HeaderPolicy::default()
+        };
+        assert_eq!(policy.allow_origins, Some(Vec::new()));
+
+        let response = app(&policy)
+            .oneshot(
+                Request::get("/x")
+                    .header(header::ORIGIN, "https://example.com")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+        // 其他安全响应头不受影响
+        assert_eq!(
+            response.headers()[header::X_CONTENT_TYPE_OPTIONS],
+            "nosniff"
+        );
+
+        let preflight = app(&policy)
+            .oneshot(
+                Request::options("/x")
+                    .header(header::ORIGIN, "https://example.com")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(preflight
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "no valid origin")]
+    fn test_invalid_origins_fail() {
+        parse_origins("https://a.exa\u{1}mple, https://b\nexample");
+    }
+}
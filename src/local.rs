@@ -0,0 +1,432 @@
+use crate::cache::{CacheManager, PackageData};
+use crate::error::AppError;
+use crate::source::PackageSource;
+use crate::{date, package, semver_utils};
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// 本地目录包来源（离线镜像）
+///
+/// 支持两种目录结构：
+/// - npm 风格：`{root}/{name}/-/{basename}-{version}.tgz`，可选的 packument 放在
+///   `{root}/{name}/index.json`（或 Verdaccio 的 `{root}/{name}/package.json`）
+/// - 扁平结构：`{root}/{name}-{version}.tgz`（`npm pack` 的输出，仅限非 scoped 包）
+///
+/// 没有 packument 时根据找到的 tarball 生成元信息，`latest` 指向最高的正式版本。
+/// `time` 中缺少的发布时间取 tarball 的修改时间，供 `?before=` 使用。
+pub struct LocalSource {
+    root: PathBuf,
+    cache: Arc<CacheManager>,
+}
+
+impl LocalSource {
+    pub fn new(root: impl Into<PathBuf>, cache: Arc<CacheManager>) -> Self {
+        Self {
+            root: root.into(),
+            cache,
+        }
+    }
+
+    /// 获取包的元信息（带缓存），`dist.tarball` 已改写为本地文件路径
+    pub async fn fetch_package_metadata(&self, name: &str) -> Result<Arc<Value>, AppError> {
+        package::validate_name(name)?;
+
+        let cache_key = self.metadata_cache_key(name);
+        if let Some(cached) = self.cache.get_metadata(&cache_key).await {
+            tracing::debug!("Local metadata cache hit for {}", name);
+            return Ok(cached);
+        }
+
+        let root = self.root.clone();
+        let package_name = name.to_string();
+        let metadata = tokio::task::spawn_blocking(move || load_metadata(&root, &package_name))
+            .await
+            .map_err(|e| AppError::InternalError(format!("Metadata task failed: {}", e)))??;
+
+        self.cache.set_metadata(cache_key, metadata.clone()).await;
+
+        Ok(Arc::new(metadata))
+    }
+}
+
+#[async_trait]
+impl PackageSource for LocalSource {
+    fn parse_path(&self, path: &str) -> Result<(String, Option<String>, Option<String>), AppError> {
+        package::parse_path(path)
+    }
+
+    async fn resolve_version(&self, name: &str, spec: Option<&str>) -> Result<String, AppError> {
+        let metadata = self.fetch_package_metadata(name).await?;
+        semver_utils::resolve_version(&metadata, spec)
+    }
+
+    async fn resolve_version_before(
+        &self,
+        name: &str,
+        spec: Option<&str>,
+        before: i64,
+    ) -> Result<String, AppError> {
+        let metadata = self.fetch_package_metadata(name).await?;
+        semver_utils::resolve_version_before(&metadata, spec, before)
+    }
+
+    async fn list_versions(&self, name: &str) -> Result<Vec<String>, AppError> {
+        let metadata = self.fetch_package_metadata(name).await?;
+        Ok(semver_utils::sorted_versions(&metadata))
+    }
+
+    async fn fetch_package(&self, name: &str, version: &str) -> Result<Arc<PackageData>, AppError> {
+        let cache_key = self.package_cache_key(name, version);
+        if let Some(cached) = self.cache.get_package(&cache_key).await {
+            tracing::debug!("Package cache hit for local {}@{}", name, version);
+            return Ok(cached);
+        }
+
+        let metadata = self.fetch_package_metadata(name).await?;
+        let tarball_path = metadata
+            .get("versions")
+            .and_then(|v| v.get(version))
+            .and_then(|v| v.get("dist"))
+            .and_then(|d| d.get("tarball"))
+            .and_then(|t| t.as_str())
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                AppError::VersionNotFound(format!("Version {} not found for {}", version, name))
+            })?;
+
+        tracing::debug!("Reading local tarball {}", tarball_path.display());
+
+        let span = tracing::Span::current();
+        let package_data = tokio::task::spawn_blocking(move || {
+            let _guard = span.enter();
+            let bytes = std::fs::read(&tarball_path)?;
+            package::package_data_from_archive(Bytes::from(bytes))
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Extraction task failed: {}", e)))??;
+
+        let package_data = Arc::new(package_data);
+        self.cache
+            .set_package(cache_key, package_data.clone())
+            .await;
+
+        Ok(package_data)
+    }
+
+    fn metadata_cache_key(&self, name: &str) -> String {
+        format!("metadata:local/{}", name)
+    }
+
+    fn package_cache_key(&self, name: &str, version: &str) -> String {
+        format!("package:local/{}@{}", name, version)
+    }
+}
+
+/// 读取或生成包的元信息
+fn load_metadata(root: &Path, name: &str) -> Result<Value, AppError> {
+    let package_dir = root.join(name);
+    let basename = name.rsplit('/').next().unwrap_or(name);
+
+    // tarball 可能出现的目录
+    let mut tarball_dirs = vec![package_dir.join("-"), package_dir.clone()];
+    if !name.starts_with('@') {
+        tarball_dirs.push(root.to_path_buf());
+    }
+
+    let packument = ["index.json", "package.json"]
+        .iter()
+        .map(|file| package_dir.join(file))
+        .find(|path| path.is_file());
+
+    let mut metadata = match packument {
+        Some(path) => {
+            let mut metadata: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+            localize_tarballs(&mut metadata, &tarball_dirs);
+            metadata
+        }
+        None => synthesize_metadata(name, basename, &tarball_dirs)?,
+    };
+
+    let has_versions = metadata
+        .get("versions")
+        .and_then(|v| v.as_object())
+        .map(|v| !v.is_empty())
+        .unwrap_or(false);
+
+    if !has_versions {
+        return Err(AppError::PackageNotFound(format!(
+            "Package '{}' not found",
+            name
+        )));
+    }
+
+    fill_publish_times(&mut metadata);
+    Ok(metadata)
+}
+
+/// 补全 `time` 中缺少的发布时间，取本地 tarball 的修改时间
+fn fill_publish_times(metadata: &mut Value) {
+    let Some(versions) = metadata.get("versions").and_then(|v| v.as_object()) else {
+        return;
+    };
+    let published: Vec<(String, String)> = versions
+        .iter()
+        .filter_map(|(version, manifest)| {
+            let path = manifest.get("dist")?.get("tarball")?.as_str()?;
+            let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+            let millis = modified.duration_since(UNIX_EPOCH).ok()?.as_millis();
+            Some((version.clone(), date::format_timestamp(millis as i64)))
+        })
+        .collect();
+
+    let Some(object) = metadata.as_object_mut() else {
+        return;
+    };
+    if let Some(time) = object
+        .entry("time")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+    {
+        for (version, published) in published {
+            time.entry(version).or_insert(Value::String(published));
+        }
+    }
+}
+
+/// 将 packument 中的 tarball URL 改写为本地路径，本地没有 tarball 的版本会被移除
+fn localize_tarballs(metadata: &mut Value, tarball_dirs: &[PathBuf]) {
+    let Some(versions) = metadata.get_mut("versions").and_then(|v| v.as_object_mut()) else {
+        return;
+    };
+
+    versions.retain(|version, manifest| {
+        let file_name = manifest
+            .get("dist")
+            .and_then(|d| d.get("tarball"))
+            .and_then(|t| t.as_str())
+            .and_then(|url| url.rsplit('/').next())
+            .filter(|f| is_safe_file_name(f))
+            .map(str::to_string);
+
+        let local = file_name.and_then(|file_name| {
+            tarball_dirs
+                .iter()
+                .map(|dir| dir.join(&file_name))
+                .find(|path| path.is_file())
+        });
+
+        match local {
+            Some(path) => {
+                manifest["dist"]["tarball"] = Value::String(path.to_string_lossy().into_owned());
+                true
+            }
+            None => {
+                tracing::debug!("No local tarball for version {}", version);
+                false
+            }
+        }
+    });
+
+    // 移除指向已删除版本的 dist-tag
+    let available: Vec<String> = versions.keys().cloned().collect();
+    if let Some(tags) = metadata
+        .get_mut("dist-tags")
+        .and_then(|v| v.as_object_mut())
+    {
+        tags.retain(|_, v| v.as_str().map(|v| available.iter().any(|a| a == v)) == Some(true));
+    }
+}
+
+/// 根据目录中的 `{basename}-{version}.tgz` 文件生成元信息
+fn synthesize_metadata(
+    name: &str,
+    basename: &str,
+    tarball_dirs: &[PathBuf],
+) -> Result<Value, AppError> {
+    let prefix = format!("{}-", basename);
+    let mut versions = Map::new();
+
+    for dir in tarball_dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(version) = file_name
+                .strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".tgz"))
+            else {
+                continue;
+            };
+            // 防止 `foo-bar-1.0.0.tgz` 被当作 `foo` 的版本 `bar-1.0.0`
+            if node_semver::Version::parse(version).is_err() || versions.contains_key(version) {
+                continue;
+            }
+            versions.insert(
+                version.to_string(),
+                json!({
+                    "name": name,
+                    "version": version,
+                    "dist": { "tarball": entry.path().to_string_lossy() },
+                }),
+            );
+        }
+    }
+
+    let mut metadata = json!({
+        "name": name,
+        "dist-tags": {},
+        "versions": versions,
+    });
+
+    // latest 指向最高的正式版本，没有正式版本时指向最高版本
+    let sorted = semver_utils::sorted_versions(&metadata);
+    let latest = sorted
+        .iter()
+        .rev()
+        .find(|v| !v.contains('-'))
+        .or_else(|| sorted.last());
+    if let Some(latest) = latest {
+        metadata["dist-tags"]["latest"] = Value::String(latest.clone());
+    }
+
+    Ok(metadata)
+}
+
+fn is_safe_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['\\', '\0', ':']) && name != ".."
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::write_tarball;
+
+    fn fixture() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path();
+
+        // npm 风格目录，无 packument
+        write_tarball(&dir.join("@scope/ui/-/ui-1.0.0.tgz"), "@scope/ui", "1.0.0");
+        write_tarball(&dir.join("@scope/ui/-/ui-1.1.0.tgz"), "@scope/ui", "1.1.0");
+        write_tarball(
+            &dir.join("@scope/ui/-/ui-2.0.0-beta.1.tgz"),
+            "@scope/ui",
+            "2.0.0-beta.1",
+        );
+
+        // 扁平结构
+        write_tarball(&dir.join("left-pad-1.3.0.tgz"), "left-pad", "1.3.0");
+        write_tarball(&dir.join("left-1.0.0.tgz"), "left", "1.0.0");
+
+        // 带 packument 的目录，2.0.0 没有本地 tarball
+        write_tarball(&dir.join("tagged/-/tagged-1.0.0.tgz"), "tagged", "1.0.0");
+        std::fs::write(
+            dir.join("tagged/index.json"),
+            r#"{
+                "name": "tagged",
+                "dist-tags": {"latest": "1.0.0", "next": "2.0.0"},
+                "versions": {
+                    "1.0.0": {"dist": {"tarball": "https://registry.npmjs.org/tagged/-/tagged-1.0.0.tgz"}},
+                    "2.0.0": {"dist": {"tarball": "https://registry.npmjs.org/tagged/-/tagged-2.0.0.tgz"}}
+                }
+            }"#,
+        )
+        .unwrap();
+
+        root
+    }
+
+    #[tokio::test]
+    async fn test_local_source() {
+        let root = fixture();
+        let source = LocalSource::new(root.path(), Arc::new(CacheManager::new()));
+
+        // 生成的元信息：latest 跳过预发布版本
+        assert_eq!(
+            source.resolve_version("@scope/ui", None).await.unwrap(),
+            "1.1.0"
+        );
+        assert_eq!(
+            source.list_versions("@scope/ui").await.unwrap(),
+            vec!["1.0.0", "1.1.0", "2.0.0-beta.1"]
+        );
+        let data = source.fetch_package("@scope/ui", "1.0.0").await.unwrap();
+        assert_eq!(data.files.read("index.js").unwrap().unwrap(), &b"1.0.0"[..]);
+
+        // 扁平结构，不会把 left-pad 当作 left 的版本
+        assert_eq!(
+            source.list_versions("left-pad").await.unwrap(),
+            vec!["1.3.0"]
+        );
+        assert_eq!(source.list_versions("left").await.unwrap(), vec!["1.0.0"]);
+
+        // packument 中缺少本地 tarball 的版本被移除
+        assert_eq!(source.list_versions("tagged").await.unwrap(), vec!["1.0.0"]);
+        assert_eq!(
+            source.resolve_version("tagged", Some("^1")).await.unwrap(),
+            "1.0.0"
+        );
+        assert!(source
+            .resolve_version("tagged", Some("next"))
+            .await
+            .is_err());
+
+        assert!(matches!(
+            source.resolve_version("missing", None).await,
+            Err(AppError::PackageNotFound(_))
+        ));
+        assert!(matches!(
+            source.resolve_version("../etc", None).await,
+            Err(AppError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_before_date() {
+        let root = fixture();
+        let set_mtime = |path: &str, date: &str| {
+            let millis = date::parse_timestamp(date).unwrap() as u64;
+            std::fs::File::options()
+                .write(true)
+                .open(root.path().join(path))
+                .unwrap()
+                .set_modified(UNIX_EPOCH + std::time::Duration::from_millis(millis))
+                .unwrap();
+        };
+        set_mtime("@scope/ui/-/ui-1.0.0.tgz", "2020-01-01");
+        set_mtime("@scope/ui/-/ui-1.1.0.tgz", "2022-01-01");
+        set_mtime("@scope/ui/-/ui-2.0.0-beta.1.tgz", "2023-01-01");
+        let source = LocalSource::new(root.path(), Arc::new(CacheManager::new()));
+
+        let before = |date: &str| date::parse_timestamp(date).unwrap();
+        assert_eq!(
+            source
+                .resolve_version_before("@scope/ui", None, before("2021-01-01"))
+                .await
+                .unwrap(),
+            "1.0.0"
+        );
+        assert_eq!(
+            source
+                .resolve_version_before("@scope/ui", Some("^1"), before("2024-01-01"))
+                .await
+                .unwrap(),
+            "1.1.0"
+        );
+        assert!(matches!(
+            source
+                .resolve_version_before("@scope/ui", None, before("2019-01-01"))
+                .await,
+            Err(AppError::VersionNotFound(_))
+        ));
+
+        // packument 没有 time 字段时同样使用 tarball 的修改时间
+        let metadata = source.fetch_package_metadata("tagged").await.unwrap();
+        assert!(metadata["time"]["1.0.0"].is_string());
+    }
+}
@@ -1,38 +1,83 @@
 use axum::{
-    extract::{Path, State},
-    response::{Html, Response},
+    extract::{Query, State},
+    http::{request::Parts, HeaderMap, HeaderValue, Method},
+    response::{Html, IntoResponse, Response},
     routing::get,
-    Router,
+    Extension, Json, Router,
 };
+use serde::Deserialize;
+use serde_json::json;
 use std::sync::Arc;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
+mod access;
+mod admin;
 mod cache;
+mod changes;
+mod compression;
+mod date;
 mod error;
+mod github;
+mod headers;
+mod local;
 mod npm;
 mod package;
+mod prewarm;
+mod ratelimit;
 mod response;
 mod semver_utils;
+mod shutdown;
+mod source;
+mod sri;
+mod tarball;
+mod telemetry;
+#[cfg(test)]
+mod testutil;
+mod upstream;
 
-use cache::CacheManager;
+use access::CacheStatus;
+use cache::{CacheManager, PackageData};
 use error::AppError;
+use source::{PackageSource, SourceRegistry};
 
 #[derive(Clone)]
 struct AppState {
     cache: Arc<CacheManager>,
-    registry: String,
-    http_client: reqwest::Client,
+    sources: Arc<SourceRegistry>,
+    active_content_policy: headers::ActiveContentPolicy,
+    rate_limits: Arc<ratelimit::RateLimitPolicy>,
+    /// 对外的站点地址（`PUBLIC_URL`），用于生成 SRI 标签中的链接
+    public_url: Option<Arc<str>>,
 }
 
 #[tokio::main]
 async fn main() {
-    // 初始化日志
+    // 子命令：byr-jsdelivr prewarm <manifest> [--pin] [--server <url>]
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("prewarm") {
+        if let Err(e) = prewarm::run_cli(&args[1..]).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // 初始化日志，访问日志单独以 JSON 行输出（ACCESS_LOG=off 关闭）
+    let access_log = std::env::var("ACCESS_LOG").map_or(true, |v| v != "off");
+    // 配置了 OTEL_EXPORTER_OTLP_ENDPOINT 或 OTEL_TRACES_EXPORTER 时导出链路
+    let telemetry = telemetry::Telemetry::from_env();
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "byr_jsdelivr=debug,tower_http=debug".into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(
+            tracing_subscriber::fmt::layer().with_filter(tracing_subscriber::filter::filter_fn(
+                |metadata| metadata.target() != access::TARGET,
+            )),
+        )
+        .with(access_log.then_some(access::JsonLines))
+        .with(telemetry.as_ref().map(|telemetry| telemetry.layer()))
         .init();
 
     // 读取环境变量
@@ -45,24 +90,127 @@ async fn main() {
 
     tracing::info!("Using npm registry: {}", registry);
 
+    let header_policy = headers::HeaderPolicy::from_env();
+
     // 初始化应用状态
-    let cache = Arc::new(CacheManager::new());
+    // 元信息缓存时长（秒），订阅了 registry 变更时可以调大
+    let cache = Arc::new(match std::env::var("METADATA_CACHE_TTL") {
+        Ok(ttl) => CacheManager::with_metadata_ttl(std::time::Duration::from_secs(
+            ttl.parse()
+                .expect("METADATA_CACHE_TTL must be a number of seconds"),
+        )),
+        Err(_) => CacheManager::new(),
+    });
+    let upstream_config = upstream::UpstreamConfig::from_env();
     let http_client = reqwest::Client::builder()
         .user_agent("byr-jsdelivr/0.1.0")
+        .connect_timeout(upstream_config.connect_timeout)
         .build()
         .expect("Failed to create HTTP client");
+    let upstream = Arc::new(upstream::UpstreamClient::new(
+        http_client.clone(),
+        upstream_config,
+    ));
+
+    // 注册包来源：/npm/、/gh/ 以及配置了本地目录时的 /local/
+    let npm: Arc<dyn PackageSource> = Arc::new(npm::NpmSource::new(
+        upstream.clone(),
+        registry,
+        cache.clone(),
+    ));
+
+    // 订阅 registry 的变更，及时刷新缓存的元信息
+    if let Ok(feed_url) = std::env::var("CHANGES_FEED_URL") {
+        let since = std::env::var("CHANGES_SINCE").unwrap_or_else(|_| "now".to_string());
+        let follower = changes::ChangesFollower::new(
+            http_client.clone(),
+            feed_url,
+            npm.clone(),
+            cache.clone(),
+        );
+        tokio::spawn(follower.run(since));
+    }
+
+    let github: Arc<dyn PackageSource> =
+        Arc::new(github::GitHubSource::from_env(upstream, cache.clone()));
+    let local: Option<Arc<dyn PackageSource>> =
+        std::env::var("LOCAL_PACKAGES_DIR").ok().map(|dir| {
+            tracing::info!("Serving local packages from {}", dir);
+            Arc::new(local::LocalSource::new(dir, cache.clone())) as Arc<dyn PackageSource>
+        });
+
+    // 未加前缀的路径使用的来源，离线环境可以设置为 local
+    let default_source = std::env::var("DEFAULT_SOURCE").unwrap_or_else(|_| "npm".to_string());
+    let default = match (default_source.as_str(), &local) {
+        ("npm", _) => npm.clone(),
+        ("local", Some(local)) => local.clone(),
+        ("local", None) => panic!("DEFAULT_SOURCE=local requires LOCAL_PACKAGES_DIR"),
+        (other, _) => panic!("Unknown DEFAULT_SOURCE: {}", other),
+    };
+    tracing::info!("Default package source: {}", default_source);
+
+    let mut sources = SourceRegistry::new(default);
+    sources.register("npm", npm);
+    sources.register("gh", github);
+    if let Some(local) = local {
+        sources.register("local", local);
+    }
+
+    let sources = Arc::new(sources);
+
+    let admin_state = admin::AdminState {
+        token: std::env::var("ADMIN_TOKEN").ok().map(Arc::from),
+        hook_secret: std::env::var("REGISTRY_HOOK_SECRET").ok().map(Arc::from),
+        sources: sources.clone(),
+        cache: cache.clone(),
+        prewarm_jobs: Arc::default(),
+    };
+
+    // 启动时预热：PREWARM_MANIFEST 指向 lockfile 或 pkg@ver 列表
+    if let Ok(manifest) = std::env::var("PREWARM_MANIFEST") {
+        let pin = std::env::var("PREWARM_PIN").map(|v| v == "true" || v == "1") == Ok(true);
+        match std::fs::read_to_string(&manifest)
+            .map_err(AppError::from)
+            .and_then(|content| prewarm::parse_manifest(&content))
+        {
+            Ok(specs) => {
+                tracing::info!("Prewarming {} packages from {}", specs.len(), manifest);
+                tokio::spawn(prewarm::prewarm(sources.clone(), cache.clone(), specs, pin));
+            }
+            Err(e) => tracing::error!("Failed to load prewarm manifest {}: {}", manifest, e),
+        }
+    }
+
+    let rate_limits = Arc::new(ratelimit::RateLimitPolicy::from_env());
 
     let state = AppState {
         cache,
-        registry,
-        http_client,
+        sources,
+        active_content_policy: headers::ActiveContentPolicy::from_env(),
+        rate_limits: rate_limits.clone(),
+        public_url: std::env::var("PUBLIC_URL").ok().map(Arc::from),
     };
 
+    let readiness = shutdown::Readiness::default();
+
     // 构建路由
-    let app = Router::new()
-        .route("/", get(root_handler))
-        .route("/*path", get(package_handler))
-        .with_state(state);
+    let app = header_policy
+        .apply(
+            Router::new()
+                .route("/", get(root_handler))
+                // GET 同时处理 HEAD；OPTIONS 由 CORS 层统一应答
+                .route("/-/sri/*path", get(sri_handler))
+                .route("/*path", get(package_handler))
+                .route_layer(axum::middleware::from_fn_with_state(
+                    rate_limits,
+                    ratelimit::middleware,
+                ))
+                .with_state(state)
+                .merge(admin::router(admin_state))
+                .merge(shutdown::router(readiness.clone()))
+                .layer(axum::middleware::from_fn(error::json_errors)),
+        )
+        .layer(axum::middleware::from_fn(access::middleware));
 
     // 启动服务器
     let addr = format!("0.0.0.0:{}", port);
@@ -72,9 +220,20 @@ async fn main() {
 
     tracing::info!("Server listening on {}", addr);
 
-    axum::serve(listener, app)
-        .await
-        .expect("Server failed to start");
+    // 收到 SIGTERM / SIGINT 后等待进行中的请求完成再退出
+    shutdown::serve(
+        listener,
+        app,
+        readiness,
+        shutdown::ShutdownConfig::from_env(),
+        shutdown::signal(),
+    )
+    .await
+    .expect("Server failed to start");
+
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
 }
 
 async fn root_handler() -> Html<&'static str> {
@@ -90,16 +249,18 @@ async fn root_handler() -> Html<&'static str> {
             <p>A minimal jsDelivr-like CDN service for npm packages.</p>
             <h2>Usage:</h2>
             <ul>
-                <li><code>/package</code> - Get the entry file of the latest version</li>
+                <li><code>/package</code> - Get the entry file of the latest version (also available as <code>/npm/package</code>)</li>
                 <li><code>/package@version</code> - Get the entry file of a specific version</li>
                 <li><code>/package@version/</code> - List directory contents</li>
                 <li><code>/package@version/path/to/file.js</code> - Get a specific file</li>
+                <li><code>/gh/user/repo@ref/path/to/file.js</code> - Get a file from a GitHub repository (tag, branch or commit)</li>
             </ul>
             <h2>Examples:</h2>
             <ul>
                 <li><a href="/react">/react</a></li>
                 <li><a href="/vue@3.3.4/">/vue@3.3.4/</a></li>
                 <li><a href="/lodash@4.17.21/lodash.js">/lodash@4.17.21/lodash.js</a></li>
+                <li><a href="/gh/jquery/jquery@3.7.1/dist/">/gh/jquery/jquery@3.7.1/dist/</a></li>
             </ul>
         </body>
         </html>
@@ -109,60 +270,272 @@ async fn root_handler() -> Html<&'static str> {
 
 async fn package_handler(
     State(state): State<AppState>,
-    Path(path): Path<String>,
+    Query(query): Query<ResolveQuery>,
+    Extension(client): Extension<ratelimit::ClientIp>,
+    request: Parts,
 ) -> Result<Response, AppError> {
-    tracing::debug!("Handling request for path: {}", path);
+    let target = resolve(&state, raw_path(&request, "/"), &query, client).await?;
+    let mut response = serve_package(&state, &target, &request).await?;
+    response::set_cache_control(&mut response, target.is_exact());
+    Ok(response)
+}
+
+/// `GET /-/sri/{package}@{version}/{file}`：返回带 SRI 哈希、可以直接粘贴的 `<script>` / `<link>` 标签
+///
+/// 标签中的地址使用解析后的确切版本。偏好 JSON 时返回地址、三种哈希与标签。
+async fn sri_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ResolveQuery>,
+    Extension(client): Extension<ratelimit::ClientIp>,
+    request: Parts,
+) -> Result<Response, AppError> {
+    let target = resolve(&state, raw_path(&request, "/-/sri/"), &query, client).await?;
+    let file_path = match target.file_path.as_deref() {
+        None => package::resolve_entry_file(&target.package)?,
+        Some(p) if p.ends_with('/') || p.is_empty() => {
+            return Err(AppError::InvalidRequest(
+                "SRI hashes are only available for files".to_string(),
+            ))
+        }
+        Some(p) => p.to_string(),
+    };
+    let info = target
+        .package
+        .files
+        .info(&file_path)
+        .ok_or_else(|| AppError::NotFound(format!("File '{}' not found", file_path)))?;
+
+    let url = format!(
+        "{}/{}{}@{}/{}",
+        public_url(&state, &request.headers),
+        target.prefix,
+        target.name,
+        target.version,
+        file_path
+    );
+    let tag = sri::tag(&url, &file_path, &info.integrity);
+
+    let mut response = if error::prefers_json(&request.headers) {
+        Json(json!({
+            "url": url,
+            "integrity": info.integrity.to_json(),
+            "tag": tag,
+        }))
+        .into_response()
+    } else {
+        let tag = tag.ok_or_else(|| {
+            AppError::InvalidRequest(format!(
+                "Cannot build a tag for '{}', only JavaScript and CSS files are supported",
+                file_path
+            ))
+        })?;
+        tag.into_response()
+    };
+    response::set_cache_control(&mut response, target.is_exact());
+    Ok(response)
+}
+
+/// 生成链接使用的站点地址：`PUBLIC_URL`，未配置时取请求的 Host
+fn public_url(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(url) = &state.public_url {
+        return url.trim_end_matches('/').to_string();
+    }
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v: &HeaderValue| v.to_str().ok())
+    };
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    let host = header("host").unwrap_or("localhost");
+    format!("{}://{}", scheme, host)
+}
+
+/// 已解析出确切版本的请求目标
+struct Target<'a> {
+    source: &'a dyn PackageSource,
+    /// 来源的 URL 前缀（如 `gh/`），生成链接时使用
+    prefix: &'a str,
+    name: String,
+    /// 请求中的版本说明（范围、标签等）
+    spec: Option<String>,
+    version: String,
+    /// 来源的包缓存键，文件与压缩缓存以它为前缀，便于按包清除
+    key: String,
+    package: Arc<PackageData>,
+    file_path: Option<String>,
+}
+
+impl Target<'_> {
+    /// 请求中写明了确切版本，内容不会再变化
+    fn is_exact(&self) -> bool {
+        self.spec.as_deref() == Some(self.version.as_str())
+    }
+}
+
+/// 请求路径去掉路由前缀后未经解码的部分（路由的 `*path` 已被解码），
+/// 由各来源的 `parse_path` 拆分后再逐段解码
+fn raw_path<'a>(request: &'a Parts, route: &str) -> &'a str {
+    request.uri.path().strip_prefix(route).unwrap_or_default()
+}
+
+/// 影响版本解析的查询参数
+#[derive(Debug, Default, Deserialize)]
+struct ResolveQuery {
+    /// 只考虑该时间之前发布的版本（`2024-01-01` 或 ISO 8601 时间）
+    before: Option<String>,
+}
 
-    // 解析路径
-    let (package_name, version_str, file_path) = package::parse_path(&path)?;
+/// 选择来源、解析路径与版本并获取包文件
+async fn resolve<'a>(
+    state: &'a AppState,
+    path: &str,
+    query: &ResolveQuery,
+    client: ratelimit::ClientIp,
+) -> Result<Target<'a>, AppError> {
+    let before = match query.before.as_deref() {
+        Some(before) => Some(date::parse_timestamp(before).ok_or_else(|| {
+            AppError::InvalidRequest(format!(
+                "Invalid date '{}', expected e.g. 2024-01-01 or 2024-01-01T00:00:00Z",
+                before
+            ))
+        })?),
+        None => None,
+    };
 
+    // 根据前缀选择包来源并解析路径，前缀来源无法解析路径时退回默认来源
+    state
+        .sources
+        .resolve_with_fallback(path, |prefix, source, parsed| {
+            resolve_package(state, prefix, source, parsed, before, client)
+        })
+        .await
+}
+
+/// 在选定的来源中解析版本并获取包文件
+async fn resolve_package<'a>(
+    state: &'a AppState,
+    prefix: &'a str,
+    source: &'a Arc<dyn PackageSource>,
+    (package_name, version_str, file_path): (String, Option<String>, Option<String>),
+    before: Option<i64>,
+    client: ratelimit::ClientIp,
+) -> Result<Target<'a>, AppError> {
     tracing::debug!(
-        "Parsed: package={}, version={:?}, file={:?}",
+        "Parsed: source={:?}, package={}, version={:?}, file={:?}",
+        prefix,
         package_name,
         version_str,
         file_path
     );
 
-    // 获取包的元信息
-    let metadata = npm::fetch_package_metadata(
-        &state.http_client,
-        &state.registry,
-        &package_name,
-        &state.cache,
-    )
-    .await?;
+    // 需要访问上游的请求额外计入上游配额（每个请求最多一次）
+    let metadata_miss = !state
+        .cache
+        .contains_metadata(&source.metadata_cache_key(&package_name));
+    access::record_metadata_cache(if metadata_miss {
+        CacheStatus::Miss
+    } else {
+        CacheStatus::Hit
+    });
+    if metadata_miss {
+        state.rate_limits.check_upstream(client).await?;
+    }
 
     // 解析版本
-    let version = semver_utils::resolve_version(&metadata, version_str.as_deref())?;
+    let version = match before {
+        Some(before) => {
+            source
+                .resolve_version_before(&package_name, version_str.as_deref(), before)
+                .await?
+        }
+        None => {
+            source
+                .resolve_version(&package_name, version_str.as_deref())
+                .await?
+        }
+    };
 
-    tracing::debug!("Resolved version: {}", version);
+    access::record_package(&format!("{}{}", prefix, package_name), &version);
+
+    let package_key = source.package_cache_key(&package_name, &version);
+    let package_cache = if state.cache.is_pinned(&package_key) {
+        CacheStatus::Pinned
+    } else if state.cache.contains_package(&package_key) {
+        CacheStatus::Hit
+    } else {
+        CacheStatus::Miss
+    };
+    access::record_package_cache(package_cache);
+    if !metadata_miss && package_cache == CacheStatus::Miss {
+        state.rate_limits.check_upstream(client).await?;
+    }
 
     // 获取包文件
-    let package_data = package::fetch_package(
-        &state.http_client,
-        &state.registry,
-        &package_name,
-        &version,
-        &metadata,
-        &state.cache,
-    )
-    .await?;
+    let package = source.fetch_package(&package_name, &version).await?;
+
+    Ok(Target {
+        source: source.as_ref(),
+        prefix,
+        name: package_name,
+        spec: version_str,
+        version,
+        key: package_key,
+        package,
+        file_path,
+    })
+}
+
+/// 根据请求的文件路径返回入口文件、目录列表或指定文件
+///
+/// HEAD 请求返回相同的响应头，不带响应体。
+async fn serve_package(
+    state: &AppState,
+    target: &Target<'_>,
+    request: &Parts,
+) -> Result<Response, AppError> {
+    let headers = &request.headers;
+    let head = request.method == Method::HEAD;
+    let package_data = target.package.as_ref();
+    let package_key = target.key.as_str();
 
     // 根据请求类型返回不同内容
-    match file_path {
+    match target.file_path.as_deref() {
         None => {
             // 返回入口文件
-            let entry_file = package::resolve_entry_file(&package_data)?;
-            response::file_response(&package_data, &entry_file)
+            let entry_file = package::resolve_entry_file(package_data)?;
+            let mut response = response::file_response(
+                package_data,
+                &entry_file,
+                package_key,
+                headers,
+                head,
+                &state.cache,
+            )
+            .await?;
+            state.active_content_policy.apply(&mut response)?;
+            Ok(response)
         }
-        Some(ref p) if p.ends_with('/') || p.is_empty() => {
+        Some(p) if p.ends_with('/') || p.is_empty() => {
             // 返回目录列表
             let dir_path = p.trim_end_matches('/');
-            response::directory_listing(&package_data, dir_path, &package_name, &version)
+            let versions = target.source.list_versions(&target.name).await?;
+            response::directory_listing(
+                package_data,
+                dir_path,
+                &format!("{}{}", target.prefix, target.name),
+                &target.version,
+                &versions,
+                headers,
+                head,
+            )
         }
-        Some(ref p) => {
+        Some(p) => {
             // 返回指定文件
-            response::file_response(&package_data, p)
+            let mut response =
+                response::file_response(package_data, p, package_key, headers, head, &state.cache)
+                    .await?;
+            state.active_content_policy.apply(&mut response)?;
+            Ok(response)
         }
     }
 }
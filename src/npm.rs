@@ -1,21 +1,94 @@
-use crate::cache::CacheManager;
+use crate::cache::{CacheManager, PackageData};
 use crate::error::AppError;
-use reqwest::Client;
+use crate::source::PackageSource;
+use crate::upstream::UpstreamClient;
+use crate::{package, semver_utils};
+use async_trait::async_trait;
 use serde_json::Value;
 use std::sync::Arc;
 
+/// npm registry 包来源
+pub struct NpmSource {
+    client: Arc<UpstreamClient>,
+    registry: String,
+    cache: Arc<CacheManager>,
+}
+
+impl NpmSource {
+    pub fn new(
+        client: Arc<UpstreamClient>,
+        registry: impl Into<String>,
+        cache: Arc<CacheManager>,
+    ) -> Self {
+        Self {
+            client,
+            registry: registry.into(),
+            cache,
+        }
+    }
+
+    async fn metadata(&self, name: &str) -> Result<Arc<Value>, AppError> {
+        fetch_package_metadata(&self.client, &self.registry, name, &self.cache).await
+    }
+}
+
+#[async_trait]
+impl PackageSource for NpmSource {
+    fn parse_path(&self, path: &str) -> Result<(String, Option<String>, Option<String>), AppError> {
+        package::parse_path(path)
+    }
+
+    async fn resolve_version(&self, name: &str, spec: Option<&str>) -> Result<String, AppError> {
+        let metadata = self.metadata(name).await?;
+        semver_utils::resolve_version(&metadata, spec)
+    }
+
+    async fn resolve_version_before(
+        &self,
+        name: &str,
+        spec: Option<&str>,
+        before: i64,
+    ) -> Result<String, AppError> {
+        let metadata = self.metadata(name).await?;
+        semver_utils::resolve_version_before(&metadata, spec, before)
+    }
+
+    async fn list_versions(&self, name: &str) -> Result<Vec<String>, AppError> {
+        let metadata = self.metadata(name).await?;
+        Ok(semver_utils::sorted_versions(&metadata))
+    }
+
+    async fn fetch_package(&self, name: &str, version: &str) -> Result<Arc<PackageData>, AppError> {
+        let metadata = self.metadata(name).await?;
+        package::fetch_package(&self.client, name, version, &metadata, &self.cache).await
+    }
+
+    fn metadata_cache_key(&self, name: &str) -> String {
+        metadata_cache_key(name)
+    }
+
+    fn package_cache_key(&self, name: &str, version: &str) -> String {
+        package::cache_key(name, version)
+    }
+}
+
+/// npm 包元信息的缓存键
+pub fn metadata_cache_key(package_name: &str) -> String {
+    format!("metadata:{}", package_name)
+}
+
 /// 获取包的元信息
+#[tracing::instrument(skip(client, cache))]
 pub async fn fetch_package_metadata(
-    client: &Client,
+    client: &UpstreamClient,
     registry: &str,
     package_name: &str,
     cache: &CacheManager,
 ) -> Result<Arc<Value>, AppError> {
-    let cache_key = format!("metadata:{}", package_name);
+    let cache_key = metadata_cache_key(package_name);
 
     // 检查缓存
     if let Some(cached) = cache.get_metadata(&cache_key).await {
-        tracing::debug!("Metadata cache hit for {}", package_name);
         return Ok(cached);
     }
 
@@ -23,16 +96,12 @@ pub async fn fetch_package_metadata(
     tracing::debug!("Fetching metadata for {} from {}", package_name, registry);
 
     let url = format!("{}/{}", registry, package_name);
-    let response = client.get(&url).send().await?;
-
-    if !response.status().is_success() {
-        return Err(AppError::NotFound(format!(
-            "Package '{}' not found",
-            package_name
-        )));
-    }
-
-    let metadata: Value = response.json().await?;
+    let metadata = client.get_json(&url).await.map_err(|e| match e {
+        AppError::NotFound(_) => {
+            AppError::PackageNotFound(format!("Package '{}' not found", package_name))
+        }
+        e => e,
+    })?;
 
     // 缓存结果
     cache.set_metadata(cache_key, metadata.clone()).await;
@@ -40,44 +109,20 @@ pub async fn fetch_package_metadata(
     Ok(Arc::new(metadata))
 }
 
-/// 下载并解析 tarball
-pub async fn download_and_extract_tarball(
-    client: &Client,
+/// 下载 tarball 并建立文件索引
+pub async fn download_tarball(
+    client: &UpstreamClient,
     tarball_url: &str,
-) -> Result<std::collections::HashMap<String, Vec<u8>>, AppError> {
-    use flate2::read::GzDecoder;
-    use tar::Archive;
-
+) -> Result<PackageData, AppError> {
     tracing::debug!("Downloading tarball from {}", tarball_url);
 
-    let response = client.get(tarball_url).send().await?;
-    let bytes = response.bytes().await?;
-
-    tracing::debug!("Extracting tarball ({} bytes)", bytes.len());
-
-    let tar = GzDecoder::new(&bytes[..]);
-    let mut archive = Archive::new(tar);
-
-    let mut files = std::collections::HashMap::new();
-
-    for entry in archive.entries()? {
-        let mut entry = entry?;
-        let path = entry.path()?.to_path_buf();
-        let is_file = entry.header().entry_type().is_file();
-
-        // npm tarball 中的文件都在 "package/" 目录下
-        let path_str = path.to_string_lossy();
-        if let Some(stripped) = path_str.strip_prefix("package/") {
-            if is_file {
-                let mut contents = Vec::new();
-                std::io::Read::read_to_end(&mut entry, &mut contents)?;
-                files.insert(stripped.to_string(), contents);
-                tracing::trace!("Extracted file: {}", stripped);
-            }
-        }
-    }
+    let bytes = client.get(tarball_url).await?;
 
-    tracing::debug!("Extracted {} files", files.len());
+    tracing::debug!("Indexing tarball ({} bytes)", bytes.len());
 
-    Ok(files)
+    // 阻塞线程上没有当前 span，需要显式传入
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(|| package::package_data_from_archive(bytes)))
+        .await
+        .map_err(|e| AppError::InternalError(format!("Extraction task failed: {}", e)))?
 }
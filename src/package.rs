@@ -1,88 +1,147 @@
 use crate::cache::{CacheManager, PackageData};
 use crate::error::AppError;
 use crate::npm;
-use reqwest::Client;
+use crate::tarball;
+use crate::upstream::UpstreamClient;
+use bytes::Bytes;
 use serde_json::Value;
 use std::sync::Arc;
 
 /// 解析路径为 (包名, 版本, 文件路径)
+///
+/// ```text
+/// path    = name [ "@" version ] [ "/" [ file ] ]
+/// name    = [ "@" scope "/" ] package
+/// ```
+///
+/// 只有包名所在的路径段中的 `@` 才分隔版本，文件路径中的 `@`（如 `logo@2x.png`）原样保留。
+/// 路径未经解码：先按 `/` 与 `@` 拆分再逐段解码，`%2F`、`%40` 不会改变路径结构。
+/// 以 `/` 结尾时文件路径也以 `/` 结尾（目录列表）。
+#[tracing::instrument]
 pub fn parse_path(path: &str) -> Result<(String, Option<String>, Option<String>), AppError> {
     let path = path.trim_start_matches('/');
-
     if path.is_empty() {
         return Err(AppError::InvalidRequest("Empty path".to_string()));
     }
 
-    // 检查是否包含 @version
-    if let Some(at_pos) = path.find('@') {
-        // 处理 scoped package (@scope/package@version/file)
-        if at_pos == 0 {
-            // scoped package
-            if let Some(second_at) = path[1..].find('@') {
-                let second_at = second_at + 1;
-                let package_name = path[..second_at].to_string();
-                let rest = &path[second_at + 1..];
-
-                if let Some(slash_pos) = rest.find('/') {
-                    let version = rest[..slash_pos].to_string();
-                    let file_path = rest[slash_pos + 1..].to_string();
-                    return Ok((package_name, Some(version), Some(file_path)));
-                } else {
-                    return Ok((package_name, Some(rest.to_string()), None));
-                }
-            } else {
-                // scoped package without version
-                if let Some(slash_pos) = path.find('/') {
-                    let second_slash = path[slash_pos + 1..].find('/');
-                    if let Some(pos) = second_slash {
-                        let package_end = slash_pos + 1 + pos;
-                        let package_name = path[..package_end].to_string();
-                        let file_path = path[package_end + 1..].to_string();
-                        return Ok((package_name, None, Some(file_path)));
-                    }
-                }
-                // 只有包名
-                return Ok((path.to_string(), None, None));
-            }
-        } else {
-            // 非 scoped package (package@version/file)
-            let package_name = path[..at_pos].to_string();
-            let rest = &path[at_pos + 1..];
-
-            if let Some(slash_pos) = rest.find('/') {
-                let version = rest[..slash_pos].to_string();
-                let file_path = rest[slash_pos + 1..].to_string();
-                return Ok((package_name, Some(version), Some(file_path)));
-            } else {
-                return Ok((package_name, Some(rest.to_string()), None));
+    // 包名占一个路径段，scoped 包占两个
+    let name_segments = if path.starts_with('@') { 2 } else { 1 };
+    let mut split = path.splitn(name_segments + 1, '/');
+    let scope = if name_segments == 2 {
+        let scope = split.next().unwrap_or_default();
+        Some(decode_segment(scope)?)
+    } else {
+        None
+    };
+    let last = split
+        .next()
+        .ok_or_else(|| AppError::InvalidRequest(format!("Invalid package name: {}", path)))?;
+    let file_path = split.next().map(decode_file_path).transpose()?;
+
+    let (package, version) = match last.split_once('@') {
+        Some((package, version)) => {
+            let version = decode_segment(version)?;
+            if version.trim().is_empty() {
+                return Err(AppError::InvalidRequest(format!(
+                    "Empty version in {}",
+                    path
+                )));
             }
+            (package, Some(version))
+        }
+        None => (last, None),
+    };
+    let package = decode_segment(package)?;
+    let name = match scope {
+        Some(scope) => format!("{}/{}", scope, package),
+        // 编码的 `%40scope%2Fname` 不能变成 scoped 包名
+        None if package.starts_with('@') => {
+            return Err(AppError::InvalidRequest(format!(
+                "Invalid package name: {}",
+                package
+            )))
         }
+        None => package,
+    };
+    validate_name(&name)?;
+
+    Ok((name, version, file_path))
+}
+
+/// 按 npm 的规则（validate-npm-package-name，兼容旧包）校验包名：
+/// 最长 214 个字符，不能以 `.` 或 `_` 开头，只能包含 URL 安全的字符，
+/// scoped 包为 `@scope/name`
+pub fn validate_name(name: &str) -> Result<(), AppError> {
+    fn is_valid_part(part: &str) -> bool {
+        !part.is_empty()
+            && !part.starts_with(['.', '_'])
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._~!*'()".contains(c))
     }
 
-    // 没有 @version 的情况
-    if let Some(slash_pos) = path.find('/') {
-        let package_name = path[..slash_pos].to_string();
-        let file_path = path[slash_pos + 1..].to_string();
-        Ok((package_name, None, Some(file_path)))
+    let valid = name.len() <= 214
+        && match name.strip_prefix('@') {
+            Some(scoped) => scoped
+                .split_once('/')
+                .is_some_and(|(scope, package)| is_valid_part(scope) && is_valid_part(package)),
+            None => is_valid_part(name) && !matches!(name, "node_modules" | "favicon.ico"),
+        };
+
+    if valid {
+        Ok(())
     } else {
-        Ok((path.to_string(), None, None))
+        Err(AppError::InvalidRequest(format!(
+            "Invalid package name: {}",
+            name
+        )))
     }
 }
 
+/// 解码一个路径段（`%XX`），解码结果必须是 UTF-8
+pub fn decode_segment(segment: &str) -> Result<String, AppError> {
+    percent_encoding::percent_decode_str(segment)
+        .decode_utf8()
+        .map(|s| s.into_owned())
+        .map_err(|_| AppError::InvalidRequest(format!("Invalid percent-encoding: {}", segment)))
+}
+
+/// 逐段解码包内的文件路径，拒绝 `..`，以及解码后出现的 `/`、`\` 与 NUL
+pub fn decode_file_path(path: &str) -> Result<String, AppError> {
+    let segments = path
+        .split('/')
+        .map(|segment| {
+            let decoded = decode_segment(segment)?;
+            if decoded == ".." || decoded.contains(['/', '\\', '\0']) {
+                return Err(AppError::InvalidRequest(format!(
+                    "Invalid file path: {}",
+                    path
+                )));
+            }
+            Ok(decoded)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(segments.join("/"))
+}
+
+/// npm 包文件的缓存键
+pub fn cache_key(package_name: &str, version: &str) -> String {
+    format!("package:{}@{}", package_name, version)
+}
+
 /// 获取包文件（带缓存）
+#[tracing::instrument(skip(client, metadata, cache))]
 pub async fn fetch_package(
-    client: &Client,
-    _registry: &str,
+    client: &UpstreamClient,
     package_name: &str,
     version: &str,
     metadata: &Value,
     cache: &CacheManager,
 ) -> Result<Arc<PackageData>, AppError> {
-    let cache_key = format!("package:{}@{}", package_name, version);
+    let cache_key = cache_key(package_name, version);
 
     // 检查缓存
     if let Some(cached) = cache.get_package(&cache_key).await {
-        tracing::debug!("Package cache hit for {}@{}", package_name, version);
         return Ok(cached);
     }
 
@@ -94,31 +153,35 @@ pub async fn fetch_package(
         .and_then(|d| d.get("tarball"))
         .and_then(|t| t.as_str())
         .ok_or_else(|| {
-            AppError::NotFound(format!(
+            AppError::VersionNotFound(format!(
                 "Version {} not found for {}",
                 version, package_name
             ))
         })?;
 
-    // 下载并解压
-    let files = npm::download_and_extract_tarball(client, tarball_url).await?;
+    // 下载并建立索引
+    let package_data = Arc::new(npm::download_tarball(client, tarball_url).await?);
+
+    // 缓存结果
+    cache.set_package(cache_key, package_data.clone()).await;
+
+    Ok(package_data)
+}
 
-    // 获取 package.json
+/// 由 tarball 构建 PackageData（建立索引并读取其中的 package.json，阻塞）
+pub fn package_data_from_archive(archive: Bytes) -> Result<PackageData, AppError> {
+    let files = tarball::Index::build(archive)?;
     let package_json_str = files
-        .get("package.json")
-        .ok_or_else(|| AppError::InternalError("package.json not found in tarball".to_string()))?;
+        .read("package.json")?
+        .ok_or_else(|| AppError::InvalidArchive("package.json not found in tarball".to_string()))?;
 
-    let package_json: Value = serde_json::from_slice(package_json_str)?;
+    let package_json: Value = serde_json::from_slice(&package_json_str)
+        .map_err(|e| AppError::InvalidArchive(format!("Invalid package.json in tarball: {}", e)))?;
 
-    let package_data = PackageData {
+    Ok(PackageData {
         files,
         package_json,
-    };
-
-    // 缓存结果
-    cache.set_package(cache_key, package_data.clone()).await;
-
-    Ok(Arc::new(package_data))
+    })
 }
 
 /// 解析入口文件
@@ -156,7 +219,7 @@ pub fn resolve_entry_file(package_data: &PackageData) -> Result<String, AppError
     }
 
     // 4. 默认尝试 index.js
-    if package_data.files.contains_key("index.js") {
+    if package_data.files.contains("index.js") {
         return Ok("index.js".to_string());
     }
 
@@ -168,6 +231,7 @@ pub fn resolve_entry_file(package_data: &PackageData) -> Result<String, AppError
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil;
 
     #[test]
     fn test_parse_path() {
@@ -195,4 +259,181 @@ mod tests {
         assert_eq!(ver, Some("3.3.4".to_string()));
         assert_eq!(file, Some("index.js".to_string()));
     }
+
+    fn parse(path: &str) -> (String, Option<String>, Option<String>) {
+        parse_path(path).unwrap()
+    }
+
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn test_parse_path_at_in_file_names() {
+        // 文件路径中的 `@` 不是版本分隔符
+        assert_eq!(
+            parse("pkg/dist/logo@2x.png"),
+            ("pkg".to_string(), None, some("dist/logo@2x.png"))
+        );
+        assert_eq!(
+            parse("pkg@1.0.0/img/a@2x@3x.png"),
+            ("pkg".to_string(), some("1.0.0"), some("img/a@2x@3x.png"))
+        );
+        assert_eq!(
+            parse("@scope/pkg/a@b.js"),
+            ("@scope/pkg".to_string(), None, some("a@b.js"))
+        );
+        assert_eq!(
+            parse("@scope/pkg@^2/a@b.js"),
+            ("@scope/pkg".to_string(), some("^2"), some("a@b.js"))
+        );
+        assert_eq!(parse("@scope/pkg"), ("@scope/pkg".to_string(), None, None));
+        // 以 `/` 结尾为目录
+        assert_eq!(parse("pkg@1/"), ("pkg".to_string(), some("1"), some("")));
+        assert_eq!(
+            parse("@scope/pkg/dist/"),
+            ("@scope/pkg".to_string(), None, some("dist/"))
+        );
+    }
+
+    #[test]
+    fn test_parse_path_decoding() {
+        assert_eq!(
+            parse("pkg@%5E1.0.0%20%7C%7C%20%5E2/a%20b.js"),
+            ("pkg".to_string(), some("^1.0.0 || ^2"), some("a b.js"))
+        );
+        // 编码的 `@` 属于文件名
+        assert_eq!(
+            parse("pkg/logo%402x.png"),
+            ("pkg".to_string(), None, some("logo@2x.png"))
+        );
+        assert_eq!(parse("pkg@1/%E4%B8%AD.js").2, some("中.js"));
+    }
+
+    #[test]
+    fn test_parse_path_rejects_invalid() {
+        for path in [
+            "",
+            "/",
+            "@scope",
+            "@scope/",
+            "@/pkg",
+            "pkg@",
+            "pkg@/index.js",
+            ".hidden",
+            "_private",
+            "node_modules",
+            "favicon.ico",
+            "a b",
+            "pkg%2Fother",
+            "%40scope%2Fpkg",
+            "pkg@1/../secret",
+            "pkg@1/a/%2E%2E/b",
+            "pkg@1/a%2Fb",
+            "pkg@1/a%5Cb",
+            "pkg@1/a%00",
+            "pkg@1/%FF",
+            "@scope/pkg/../x",
+        ] {
+            assert!(
+                matches!(parse_path(path), Err(AppError::InvalidRequest(_))),
+                "{:?} should be rejected",
+                path
+            );
+        }
+        assert!(parse_path(&"a".repeat(215)).is_err());
+        assert!(parse_path(&"a".repeat(214)).is_ok());
+        // 兼容含大写字母的旧包
+        assert_eq!(parse("JSONStream").0, "JSONStream");
+    }
+
+    mod properties {
+        use super::super::*;
+        use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+        use proptest::prelude::*;
+
+        fn name_part() -> impl Strategy<Value = String> {
+            "[a-zA-Z0-9][a-zA-Z0-9._~!*'()-]{0,20}"
+        }
+
+        fn package_name() -> impl Strategy<Value = String> {
+            prop_oneof![
+                name_part(),
+                (name_part(), name_part()).prop_map(|(scope, name)| format!("@{}/{}", scope, name)),
+            ]
+        }
+
+        fn version() -> impl Strategy<Value = String> {
+            "[0-9a-zA-Z^~<>=|.*+@ -]{0,15}[0-9a-zA-Z]"
+        }
+
+        /// 不含 `..` 的文件路径段，可以包含 `@`、空格与非 ASCII 字符
+        fn file_segment() -> impl Strategy<Value = String> {
+            "[a-zA-Z0-9@._ 中-]{1,12}".prop_filter("no parent segments", |s| s != "..")
+        }
+
+        fn encode(s: &str) -> String {
+            utf8_percent_encode(s, NON_ALPHANUMERIC).to_string()
+        }
+
+        proptest! {
+            #[test]
+            fn roundtrip(
+                name in package_name(),
+                version in proptest::option::of(version()),
+                file in proptest::option::of(proptest::collection::vec(file_segment(), 0..4)),
+            ) {
+                let mut path = name.clone();
+                if let Some(version) = &version {
+                    path.push('@');
+                    path.push_str(&encode(version));
+                }
+                if let Some(file) = &file {
+                    path.push('/');
+                    path.push_str(&file.iter().map(|s| encode(s)).collect::<Vec<_>>().join("/"));
+                }
+
+                let parsed = parse_path(&path).unwrap();
+                prop_assert_eq!(parsed, (name, version, file.map(|f| f.join("/"))));
+            }
+
+            #[test]
+            fn raw_at_in_file_path(
+                name in package_name(),
+                file in proptest::collection::vec("[a-zA-Z0-9@._-]{1,12}", 1..4),
+            ) {
+                prop_assume!(file.iter().all(|s| s != ".."));
+                let file = file.join("/");
+                let parsed = parse_path(&format!("{}/{}", name, file)).unwrap();
+                prop_assert_eq!(parsed, (name, None, Some(file)));
+            }
+
+            #[test]
+            fn arbitrary_paths_are_safe(path in "\\PC{0,40}|[@a-z%/.0-9]{0,40}") {
+                // 任意输入都不会 panic；接受的结果满足包名规则且不含 `..`
+                if let Ok((name, version, file)) = parse_path(&path) {
+                    prop_assert!(validate_name(&name).is_ok());
+                    prop_assert!(version.map_or(true, |v| !v.trim().is_empty()));
+                    if let Some(file) = file {
+                        prop_assert!(file.split('/').all(|segment| segment != ".."));
+                        prop_assert!(!file.contains(['\\', '\0']));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_package_data_requires_package_json() {
+        let data = package_data_from_archive(Bytes::from(testutil::npm_tarball("demo", "1.0.0")));
+        assert_eq!(data.unwrap().package_json["name"], "demo");
+
+        for files in [
+            &[("package/index.js", "1")][..],
+            &[("package/package.json", "{")][..],
+        ] {
+            let result = package_data_from_archive(Bytes::from(testutil::tarball(files)));
+            assert!(matches!(result, Err(AppError::InvalidArchive(_))));
+        }
+    }
 }
@@ -0,0 +1,439 @@
+use crate::cache::CacheManager;
+use crate::error::AppError;
+use crate::source::SourceRegistry;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// 同时预热的包数量
+const PREWARM_CONCURRENCY: usize = 8;
+
+/// 保留结果的后台预热任务数量
+const MAX_JOBS: usize = 32;
+
+/// 预热结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PrewarmReport {
+    pub requested: usize,
+    pub warmed: Vec<String>,
+    pub failed: Vec<PrewarmFailure>,
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrewarmFailure {
+    pub spec: String,
+    pub error: String,
+}
+
+/// 解析清单为 `包名@版本` 列表
+///
+/// 支持 `package-lock.json`（v1 ~ v3）、`pnpm-lock.yaml`（v5 ~ v9）以及
+/// 每行一个 `pkg@ver` 的纯文本列表（`#` 开头为注释，可以带 `gh/` 等来源前缀）。
+pub fn parse_manifest(content: &str) -> Result<Vec<String>, AppError> {
+    let trimmed = content.trim_start();
+
+    let specs = if trimmed.starts_with('{') {
+        let lockfile: Value = serde_json::from_str(content)
+            .map_err(|e| AppError::InvalidRequest(format!("Invalid package-lock.json: {}", e)))?;
+        parse_package_lock(&lockfile)
+    } else if trimmed.starts_with("lockfileVersion") {
+        let lockfile: serde_yaml::Value = serde_yaml::from_str(content)
+            .map_err(|e| AppError::InvalidRequest(format!("Invalid pnpm-lock.yaml: {}", e)))?;
+        parse_pnpm_lock(&lockfile)
+    } else {
+        content
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    // 去重并保持稳定顺序
+    Ok(specs
+        .into_iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
+}
+
+/// package-lock.json：v2/v3 使用 `packages`，v1 使用嵌套的 `dependencies`
+fn parse_package_lock(lockfile: &Value) -> Vec<String> {
+    let mut specs = Vec::new();
+
+    if let Some(packages) = lockfile.get("packages").and_then(|v| v.as_object()) {
+        for (path, entry) in packages {
+            // 根项目与 workspace 链接没有 tarball
+            if path.is_empty() || entry.get("link").and_then(|v| v.as_bool()) == Some(true) {
+                continue;
+            }
+            let Some(version) = entry.get("version").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            // 别名安装时真实包名在 name 字段中
+            let name = entry
+                .get("name")
+                .and_then(|v| v.as_str())
+                .or_else(|| path.rsplit_once("node_modules/").map(|(_, name)| name));
+            if let Some(name) = name {
+                push_spec(&mut specs, name, version);
+            }
+        }
+        return specs;
+    }
+
+    fn walk(dependencies: &serde_json::Map<String, Value>, specs: &mut Vec<String>) {
+        for (name, entry) in dependencies {
+            if let Some(version) = entry.get("version").and_then(|v| v.as_str()) {
+                push_spec(specs, name, version);
+            }
+            if let Some(nested) = entry.get("dependencies").and_then(|v| v.as_object()) {
+                walk(nested, specs);
+            }
+        }
+    }
+
+    if let Some(dependencies) = lockfile.get("dependencies").and_then(|v| v.as_object()) {
+        walk(dependencies, &mut specs);
+    }
+    specs
+}
+
+/// pnpm-lock.yaml 的 `packages` 键：
+/// v5 `/name/1.0.0_peer`，v6 `/name@1.0.0(peer@1)`，v9 `name@1.0.0`
+fn parse_pnpm_lock(lockfile: &serde_yaml::Value) -> Vec<String> {
+    let Some(packages) = lockfile.get("packages").and_then(|v| v.as_mapping()) else {
+        return Vec::new();
+    };
+
+    packages
+        .keys()
+        .filter_map(|key| key.as_str())
+        .filter_map(|key| {
+            let key = key.trim_start_matches('/');
+            // 去掉 peer 依赖后缀
+            let key = key.split('(').next().unwrap_or(key);
+
+            // v5 的版本在最后一段，peer 依赖以 `_` 连接（其中也可能含 `@`）
+            let v5 = key
+                .rsplit_once('/')
+                .map(|(name, version)| (name, version.split('_').next().unwrap_or(version)))
+                .filter(|(_, version)| node_semver::Version::parse(*version).is_ok());
+            let (name, version) = match v5 {
+                Some(parsed) => parsed,
+                None => {
+                    let pos = key.rfind('@').filter(|pos| *pos > 0)?;
+                    (&key[..pos], &key[pos + 1..])
+                }
+            };
+            let mut spec = Vec::new();
+            push_spec(&mut spec, name, version);
+            spec.pop()
+        })
+        .collect()
+}
+
+/// 只保留来自 registry 的精确版本（跳过 `file:`、`git+` 等），处理 `npm:` 别名
+fn push_spec(specs: &mut Vec<String>, name: &str, version: &str) {
+    let (name, version) = match version.strip_prefix("npm:") {
+        Some(alias) => match alias.rfind('@').filter(|pos| *pos > 0) {
+            Some(pos) => (&alias[..pos], &alias[pos + 1..]),
+            None => return,
+        },
+        None => (name, version),
+    };
+
+    if node_semver::Version::parse(version).is_ok() {
+        specs.push(format!("{}@{}", name, version));
+    }
+}
+
+/// 预热：解析版本并下载包文件放入缓存，`pin` 为 true 时固定这些包
+pub async fn prewarm(
+    sources: Arc<SourceRegistry>,
+    cache: Arc<CacheManager>,
+    specs: Vec<String>,
+    pin: bool,
+) -> PrewarmReport {
+    let mut report = PrewarmReport {
+        requested: specs.len(),
+        pinned: pin,
+        ..Default::default()
+    };
+
+    let semaphore = Arc::new(Semaphore::new(PREWARM_CONCURRENCY));
+    let mut tasks = JoinSet::new();
+
+    for spec in specs {
+        let sources = sources.clone();
+        let cache = cache.clone();
+        let semaphore = semaphore.clone();
+
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire().await;
+            let result = warm_one(&sources, &cache, &spec, pin).await;
+            (spec, result)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((_, Ok(resolved))) => {
+                tracing::debug!("Prewarmed {}", resolved);
+                report.warmed.push(resolved);
+            }
+            Ok((spec, Err(e))) => {
+                tracing::warn!("Failed to prewarm {}: {}", spec, e);
+                report.failed.push(PrewarmFailure {
+                    spec,
+                    error: e.to_string(),
+                });
+            }
+            Err(e) => tracing::error!("Prewarm task failed: {}", e),
+        }
+    }
+
+    report.warmed.sort();
+    tracing::info!(
+        "Prewarmed {}/{} packages ({} failed, pinned: {})",
+        report.warmed.len(),
+        report.requested,
+        report.failed.len(),
+        pin
+    );
+
+    report
+}
+
+/// 管理接口提交的后台预热任务，按提交顺序编号，只保留最近 [`MAX_JOBS`] 个的结果
+#[derive(Default)]
+pub struct PrewarmJobs {
+    next_id: AtomicU64,
+    /// 任务编号 -> 结果，`None` 表示仍在运行
+    jobs: Mutex<BTreeMap<u64, Option<PrewarmReport>>>,
+}
+
+impl PrewarmJobs {
+    /// 在后台开始预热，返回任务编号
+    pub fn spawn(
+        self: &Arc<Self>,
+        sources: Arc<SourceRegistry>,
+        cache: Arc<CacheManager>,
+        specs: Vec<String>,
+        pin: bool,
+    ) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.insert(id, None);
+            while jobs.len() > MAX_JOBS {
+                jobs.pop_first();
+            }
+        }
+
+        let jobs = self.clone();
+        tokio::spawn(async move {
+            let report = prewarm(sources, cache, specs, pin).await;
+            // 已被较新的任务挤出时不再记录
+            if let Some(slot) = jobs.jobs.lock().unwrap().get_mut(&id) {
+                *slot = Some(report);
+            }
+        });
+
+        id
+    }
+
+    /// 任务状态：不存在时为 `None`，仍在运行时为 `Some(None)`
+    pub fn get(&self, id: u64) -> Option<Option<PrewarmReport>> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+}
+
+async fn warm_one(
+    sources: &SourceRegistry,
+    cache: &CacheManager,
+    spec: &str,
+    pin: bool,
+) -> Result<String, AppError> {
+    let (prefix, source, rest) = sources.route(spec);
+    let (name, version_spec, _) = source.parse_path(rest)?;
+    let version = source
+        .resolve_version(&name, version_spec.as_deref())
+        .await?;
+    let package_data = source.fetch_package(&name, &version).await?;
+
+    if pin {
+        cache
+            .pin_package(source.package_cache_key(&name, &version), package_data)
+            .await?;
+    }
+
+    Ok(format!("{}{}@{}", prefix, name, version))
+}
+
+/// 命令行：`byr-jsdelivr prewarm <manifest> [--pin] [--server <url>]`
+///
+/// 将清单提交给运行中服务的管理接口（使用 `ADMIN_TOKEN` 认证）。
+pub async fn run_cli(args: &[String]) -> Result<(), String> {
+    let mut manifest = None;
+    let mut pin = false;
+    let mut server = format!(
+        "http://127.0.0.1:{}",
+        std::env::var("PORT").unwrap_or_else(|_| "3000".to_string())
+    );
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pin" => pin = true,
+            "--server" => {
+                server = args
+                    .next()
+                    .ok_or("--server requires a URL")?
+                    .trim_end_matches('/')
+                    .to_string();
+            }
+            path if manifest.is_none() => manifest = Some(path.to_string()),
+            other => return Err(format!("Unexpected argument: {}", other)),
+        }
+    }
+
+    let manifest =
+        manifest.ok_or("Usage: byr-jsdelivr prewarm <manifest> [--pin] [--server <url>]")?;
+    let content = std::fs::read_to_string(&manifest)
+        .map_err(|e| format!("Failed to read {}: {}", manifest, e))?;
+    let token = std::env::var("ADMIN_TOKEN").map_err(|_| "ADMIN_TOKEN is not set")?;
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/-/admin/prewarm?pin={}", server, pin))
+        .bearer_auth(&token)
+        .body(content)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("Server returned {}: {}", status, body));
+    }
+
+    // 预热在服务端后台进行，轮询任务状态直到完成
+    let job: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid response: {}", e))?;
+    let id = job["id"].as_u64().ok_or("Response has no job id")?;
+    eprintln!(
+        "Prewarm job {} started for {} packages",
+        id, job["requested"]
+    );
+
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+
+        let response = client
+            .get(format!("{}/-/admin/prewarm/{}", server, id))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!("Server returned {}: {}", status, body));
+        }
+
+        let job: Value =
+            serde_json::from_str(&body).map_err(|e| format!("Invalid response: {}", e))?;
+        if job["status"] != "running" {
+            println!("{}", body);
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_lock_v3() {
+        let lockfile = r#"{
+            "name": "app",
+            "lockfileVersion": 3,
+            "packages": {
+                "": {"name": "app", "version": "1.0.0"},
+                "node_modules/vue": {"version": "3.3.4"},
+                "node_modules/@vue/shared": {"version": "3.3.4"},
+                "node_modules/a/node_modules/lodash": {"version": "4.17.21"},
+                "node_modules/my-alias": {"name": "react", "version": "18.2.0"},
+                "node_modules/local": {"resolved": "packages/local", "link": true},
+                "packages/local": {"version": "0.0.0"}
+            }
+        }"#;
+        assert_eq!(
+            parse_manifest(lockfile).unwrap(),
+            vec![
+                "@vue/shared@3.3.4",
+                "lodash@4.17.21",
+                "react@18.2.0",
+                "vue@3.3.4"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_package_lock_v1() {
+        let lockfile = r#"{
+            "lockfileVersion": 1,
+            "dependencies": {
+                "vue": {"version": "2.7.14"},
+                "alias": {"version": "npm:react@18.2.0"},
+                "local": {"version": "file:../local"},
+                "a": {"version": "1.0.0", "dependencies": {"b": {"version": "2.0.0"}}}
+            }
+        }"#;
+        assert_eq!(
+            parse_manifest(lockfile).unwrap(),
+            vec!["a@1.0.0", "b@2.0.0", "react@18.2.0", "vue@2.7.14"]
+        );
+    }
+
+    #[test]
+    fn test_parse_pnpm_lock() {
+        let v6 = "lockfileVersion: '6.0'\npackages:\n  /vue@3.3.4:\n    resolution: {integrity: x}\n  /@vue/shared@3.3.4:\n    resolution: {integrity: x}\n  /react-dom@18.2.0(react@18.2.0):\n    resolution: {integrity: x}\n";
+        assert_eq!(
+            parse_manifest(v6).unwrap(),
+            vec!["@vue/shared@3.3.4", "react-dom@18.2.0", "vue@3.3.4"]
+        );
+
+        let v5 = "lockfileVersion: 5.4\npackages:\n  /vue/3.3.4:\n    dev: false\n  /@vue/shared/3.3.4_react@18.2.0:\n    dev: false\n";
+        assert_eq!(
+            parse_manifest(v5).unwrap(),
+            vec!["@vue/shared@3.3.4", "vue@3.3.4"]
+        );
+
+        let v9 = "lockfileVersion: '9.0'\npackages:\n  vue@3.3.4:\n    resolution: {integrity: x}\n  '@vue/shared@3.3.4':\n    resolution: {integrity: x}\n";
+        assert_eq!(
+            parse_manifest(v9).unwrap(),
+            vec!["@vue/shared@3.3.4", "vue@3.3.4"]
+        );
+    }
+
+    #[test]
+    fn test_parse_plain_list() {
+        let list =
+            "# comment\nvue@3.3.4\n\n@vue/shared@^3  # range\ngh/jquery/jquery@3.7.1\nvue@3.3.4\n";
+        assert_eq!(
+            parse_manifest(list).unwrap(),
+            vec!["@vue/shared@^3", "gh/jquery/jquery@3.7.1", "vue@3.3.4"]
+        );
+    }
+}
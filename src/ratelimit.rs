@@ -0,0 +1,301 @@
+use crate::access;
+use crate::error::AppError;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use moka::future::Cache;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 同时跟踪的客户端数量上限
+const MAX_TRACKED_CLIENTS: u64 = 100_000;
+
+/// 经过信任代理解析后的客户端地址，由 [`middleware`] 写入请求扩展
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// 令牌桶
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// 取出一个令牌，不足时返回需要等待的时间
+    fn take(&mut self, now: Instant, rate: f64, burst: f64) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// 按客户端 IP 计数的令牌桶限流器
+pub struct RateLimiter {
+    /// 每秒补充的令牌数
+    rate: f64,
+    /// 桶容量，即允许的突发请求数
+    burst: f64,
+    buckets: Cache<IpAddr, Arc<Mutex<Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        // 空闲到桶被补满之后就不必再保留
+        let idle = Duration::from_secs_f64(burst / rate) + Duration::from_secs(1);
+
+        Self {
+            rate,
+            burst,
+            buckets: Cache::builder()
+                .max_capacity(MAX_TRACKED_CLIENTS)
+                .time_to_idle(idle)
+                .build(),
+        }
+    }
+
+    /// 消耗一次配额，超出时返回建议的重试等待时间
+    pub async fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let bucket = self
+            .buckets
+            .get_with(ip, async {
+                Arc::new(Mutex::new(Bucket {
+                    tokens: self.burst,
+                    updated: now,
+                }))
+            })
+            .await;
+        let result = bucket.lock().unwrap().take(now, self.rate, self.burst);
+        result
+    }
+
+    /// 从环境变量 `{prefix}_RPS` / `{prefix}_BURST` 读取，未设置 RPS 时不限流
+    fn from_env(prefix: &str) -> Option<Self> {
+        let rate = std::env::var(format!("{}_RPS", prefix)).ok()?;
+        let rate: f64 = rate
+            .parse()
+            .ok()
+            .filter(|rate: &f64| *rate > 0.0)
+            .unwrap_or_else(|| panic!("{}_RPS must be a positive number", prefix));
+        let burst = std::env::var(format!("{}_BURST", prefix))
+            .map(|burst| {
+                burst
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{}_BURST must be a positive integer", prefix))
+            })
+            .unwrap_or(rate.ceil() as u32);
+
+        tracing::info!("{} enabled: {} requests/s, burst {}", prefix, rate, burst);
+        Some(Self::new(rate, burst))
+    }
+}
+
+/// 限流策略
+///
+/// `requests` 限制每个客户端的全部请求，`upstream` 只计入需要访问上游
+/// （元信息或包文件未缓存）的请求，通常更严格。
+#[derive(Default)]
+pub struct RateLimitPolicy {
+    pub requests: Option<RateLimiter>,
+    pub upstream: Option<RateLimiter>,
+    /// 信任其 `X-Forwarded-For` 的代理地址
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl RateLimitPolicy {
+    /// 从环境变量读取策略
+    ///
+    /// - `RATE_LIMIT_RPS` / `RATE_LIMIT_BURST`: 每个客户端的请求速率与突发数
+    /// - `UPSTREAM_RATE_LIMIT_RPS` / `UPSTREAM_RATE_LIMIT_BURST`: 未命中缓存的请求
+    /// - `TRUSTED_PROXIES`: 逗号分隔的 IP 或 CIDR
+    pub fn from_env() -> Self {
+        let trusted_proxies = std::env::var("TRUSTED_PROXIES")
+            .map(|proxies| parse_proxies(&proxies))
+            .unwrap_or_default();
+
+        Self {
+            requests: RateLimiter::from_env("RATE_LIMIT"),
+            upstream: RateLimiter::from_env("UPSTREAM_RATE_LIMIT"),
+            trusted_proxies,
+        }
+    }
+
+    /// 确定客户端地址：直连地址属于信任代理时，从右向左取
+    /// `X-Forwarded-For` 中第一个不属于信任代理的地址
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let trusted = |ip: &IpAddr| self.trusted_proxies.iter().any(|net| net.contains(ip));
+        if !trusted(&peer) {
+            return peer;
+        }
+
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+
+        let mut client = peer;
+        for hop in forwarded.into_iter().rev() {
+            match hop.parse::<IpAddr>() {
+                Ok(ip) if trusted(&ip) => client = ip,
+                Ok(ip) => return ip,
+                // 无法解析的地址不可信，停在最后一个可信的一跳
+                Err(_) => break,
+            }
+        }
+        client
+    }
+
+    /// 消耗一次上游请求配额
+    pub async fn check_upstream(&self, client: ClientIp) -> Result<(), AppError> {
+        match &self.upstream {
+            Some(limiter) => limiter.check(client.0).await.map_err(|retry_after| {
+                tracing::debug!("Upstream rate limit exceeded for {}", client.0);
+                AppError::RateLimited(retry_after)
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_proxies(proxies: &str) -> Vec<IpNet> {
+    proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .map(|proxy| {
+            proxy
+                .parse::<IpNet>()
+                .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                .unwrap_or_else(|_| panic!("Invalid TRUSTED_PROXIES entry: {}", proxy))
+        })
+        .collect()
+}
+
+/// 解析客户端地址并按 `requests` 限流
+pub async fn middleware(
+    State(policy): State<Arc<RateLimitPolicy>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let client = ClientIp(policy.client_ip(peer, request.headers()));
+    request.extensions_mut().insert(client);
+    access::record_client(client.0);
+
+    if let Some(limiter) = &policy.requests {
+        limiter.check(client.0).await.map_err(|retry_after| {
+            tracing::debug!("Rate limit exceeded for {}", client.0);
+            AppError::RateLimited(retry_after)
+        })?;
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_bucket_refill() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 2.0,
+            updated: start,
+        };
+
+        assert!(bucket.take(start, 1.0, 2.0).is_ok());
+        assert!(bucket.take(start, 1.0, 2.0).is_ok());
+        let wait = bucket.take(start, 1.0, 2.0).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(1));
+
+        // 补充的令牌不超过桶容量
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.take(later, 1.0, 2.0).is_ok());
+        assert!(bucket.take(later, 1.0, 2.0).is_ok());
+        assert!(bucket.take(later, 1.0, 2.0).is_err());
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let policy = RateLimitPolicy {
+            trusted_proxies: parse_proxies("10.0.0.0/8, 192.168.1.1"),
+            ..Default::default()
+        };
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", value.parse().unwrap());
+            headers
+        };
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        // 不信任的直连地址忽略 X-Forwarded-For
+        assert_eq!(
+            policy.client_ip(ip("203.0.113.9"), &headers("1.2.3.4")),
+            ip("203.0.113.9")
+        );
+        // 客户端伪造的前缀不会生效
+        assert_eq!(
+            policy.client_ip(ip("10.1.2.3"), &headers("6.6.6.6, 1.2.3.4, 192.168.1.1")),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            policy.client_ip(ip("192.168.1.1"), &headers("garbage, 10.0.0.5")),
+            ip("10.0.0.5")
+        );
+        assert_eq!(
+            policy.client_ip(ip("10.1.2.3"), &HeaderMap::new()),
+            ip("10.1.2.3")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_middleware_returns_429() {
+        let policy = Arc::new(RateLimitPolicy {
+            requests: Some(RateLimiter::new(0.5, 2)),
+            ..Default::default()
+        });
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(policy, middleware));
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(axum::http::Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let limited = app
+            .oneshot(axum::http::Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()["retry-after"], "2");
+    }
+}
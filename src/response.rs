@@ -1,28 +1,352 @@
-use crate::cache::PackageData;
-use crate::error::AppError;
+use crate::access::{self, CacheStatus};
+use crate::cache::{CacheManager, PackageData};
+use crate::compression::{self, Encoding};
+use crate::date;
+use crate::error::{self, AppError};
+use crate::package;
+use crate::tarball::FileInfo;
 use axum::{
-    http::header,
-    response::{Html, IntoResponse, Response},
+    body::Body,
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
 };
+use bytes::Bytes;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// SourceMap 响应头（浏览器 DevTools 使用）
+static SOURCE_MAP: HeaderName = HeaderName::from_static("sourcemap");
+/// 文件内容（解压后）的 SRI 哈希，与压缩方式无关
+pub static X_INTEGRITY: HeaderName = HeaderName::from_static("x-integrity");
+
+/// 确切版本的内容不会变化，缓存一年
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// 版本范围、标签等解析结果可能变化，与元信息缓存时长一致
+const FLOATING_CACHE_CONTROL: &str = "public, max-age=300";
 
 /// 返回文件响应
-pub fn file_response(package_data: &PackageData, file_path: &str) -> Result<Response, AppError> {
-    let file_content = package_data
+///
+/// 文件按需从压缩包中解压并缓存。根据 Accept-Encoding 协商压缩：优先使用包内自带的
+/// `.br`/`.gz` 文件，否则压缩一次后按 `{package_key}/{file_path}` 缓存。
+/// ETag 取自建立索引时计算的 sha256，`If-None-Match` 命中时直接返回 304，不需要解压文件；
+/// HEAD 请求返回与 GET 相同的响应头，但不复制响应体。
+pub async fn file_response(
+    package_data: &PackageData,
+    file_path: &str,
+    package_key: &str,
+    request_headers: &HeaderMap,
+    head: bool,
+    cache: &CacheManager,
+) -> Result<Response, AppError> {
+    let info = package_data
         .files
-        .get(file_path)
+        .info(file_path)
         .ok_or_else(|| AppError::NotFound(format!("File '{}' not found", file_path)))?;
 
     let content_type = get_content_type(file_path);
+    let negotiated = choose_encoding(
+        package_data,
+        file_path,
+        info.size,
+        content_type,
+        request_headers,
+    );
+    let compressible = negotiated.is_some() || compression::is_compressible(content_type);
+
+    let hash = info.integrity.short_sha256();
+    let negotiated_etag = etag(&hash, negotiated.as_ref().map(|(encoding, _)| *encoding));
+    if is_not_modified(request_headers, &negotiated_etag) {
+        return Ok(not_modified(negotiated_etag, compressible));
+    }
+
+    let file_content = read_file(package_data, file_path, package_key, cache).await?;
+
+    // 响应体与缓存共享同一块内存，只增加引用计数
+    let (body, encoding) = match negotiated {
+        Some((encoding, Some(precompressed))) => (
+            read_file(package_data, &precompressed, package_key, cache).await?,
+            Some(encoding),
+        ),
+        Some((encoding, None)) => {
+            match compressed_body(file_path, &file_content, package_key, encoding, cache).await {
+                Some(compressed) => (compressed, Some(encoding)),
+                // 压缩失败时返回原始内容
+                None => (file_content.clone(), None),
+            }
+        }
+        None => (file_content.clone(), None),
+    };
+
+    let mut response = body_response(body, head);
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(header::ETAG, etag(&hash, encoding));
+    if let Some(encoding) = encoding {
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+    }
+    if compressible {
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    if let Ok(value) = HeaderValue::from_str(&info.integrity.to_string()) {
+        headers.insert(X_INTEGRITY.clone(), value);
+    }
+
+    // 附带 source map 位置，方便调试
+    if let Some(map_url) = find_source_map(package_data, file_path, &file_content) {
+        if let Ok(value) = HeaderValue::from_str(&map_url) {
+            headers.insert(SOURCE_MAP.clone(), value);
+        }
+    }
+
+    Ok(response)
+}
+
+/// 选择压缩方式，返回 `(编码, 包内自带的预压缩文件路径)`；客户端不接受压缩或不值得压缩时返回 None
+fn choose_encoding(
+    package_data: &PackageData,
+    file_path: &str,
+    size: u64,
+    content_type: &str,
+    request_headers: &HeaderMap,
+) -> Option<(Encoding, Option<String>)> {
+    let encodings = compression::negotiate(request_headers);
+
+    // 包内自带的预压缩文件
+    for encoding in &encodings {
+        let precompressed = format!("{}{}", file_path, encoding.extension());
+        if package_data.files.contains(&precompressed) {
+            return Some((*encoding, Some(precompressed)));
+        }
+    }
+
+    let encoding = *encodings.first()?;
+    if size < compression::MIN_COMPRESS_SIZE as u64 || !compression::is_compressible(content_type) {
+        return None;
+    }
+    Some((encoding, None))
+}
+
+/// 读取包内文件：解压过的热点文件直接从缓存返回，否则从压缩包中解压并写入缓存
+async fn read_file(
+    package_data: &PackageData,
+    file_path: &str,
+    package_key: &str,
+    cache: &CacheManager,
+) -> Result<Bytes, AppError> {
+    let file = package_data
+        .files
+        .file(file_path)
+        .ok_or_else(|| AppError::NotFound(format!("File '{}' not found", file_path)))?;
+
+    let cache_key = format!("{}/{}", package_key, file_path);
+    if let Some(cached) = cache.get_file(&cache_key).await {
+        return Ok(cached);
+    }
+
+    // 阻塞线程上没有当前 span，需要显式传入
+    let span = tracing::Span::current();
+    let content = tokio::task::spawn_blocking(move || span.in_scope(|| file.read()))
+        .await
+        .map_err(|e| AppError::InternalError(format!("Extraction task failed: {}", e)))??;
+    cache.set_file(cache_key, content.clone()).await;
+    Ok(content)
+}
+
+/// 取出缓存的压缩结果，未缓存时压缩并写入缓存；压缩失败时返回 None
+async fn compressed_body(
+    file_path: &str,
+    file_content: &Bytes,
+    package_key: &str,
+    encoding: Encoding,
+    cache: &CacheManager,
+) -> Option<Bytes> {
+    let cache_key = format!("{}/{}:{}", package_key, file_path, encoding.as_str());
+    if let Some(cached) = cache.get_compressed(&cache_key).await {
+        access::record_compressed_cache(CacheStatus::Hit);
+        return Some(cached);
+    }
+    access::record_compressed_cache(CacheStatus::Miss);
 
-    Ok(([(header::CONTENT_TYPE, content_type)], file_content.clone()).into_response())
+    let content = file_content.clone();
+    let compressed = tokio::task::spawn_blocking(move || compression::compress(&content, encoding))
+        .await
+        .ok()?;
+    match compressed {
+        Ok(compressed) => {
+            let compressed = Bytes::from(compressed);
+            cache.set_compressed(cache_key, compressed.clone()).await;
+            Some(compressed)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to compress {}: {}", file_path, e);
+            None
+        }
+    }
+}
+
+/// 构造带 Content-Length 的响应，HEAD 请求不带响应体
+fn body_response(body: Bytes, head: bool) -> Response {
+    let length = HeaderValue::from(body.len());
+    let body = if head {
+        Body::empty()
+    } else {
+        Body::from(body)
+    };
+    let mut response = Response::new(body);
+    response
+        .headers_mut()
+        .insert(header::CONTENT_LENGTH, length);
+    response
+}
+
+/// 内容的 SHA-256（取前 128 位），用于动态生成的页面；包内文件使用索引中的哈希
+fn content_hash(content: &[u8]) -> String {
+    hex::encode(&Sha256::digest(content)[..16])
+}
+
+/// 强 ETag，不同压缩方式的表示各不相同
+fn etag(hash: &str, encoding: Option<Encoding>) -> HeaderValue {
+    let value = match encoding {
+        Some(encoding) => format!("\"{}-{}\"", hash, encoding.as_str()),
+        None => format!("\"{}\"", hash),
+    };
+    HeaderValue::from_str(&value).expect("ETag is valid ASCII")
+}
+
+/// `If-None-Match` 是否包含当前 ETag（弱比较）
+fn is_not_modified(request_headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let etag = etag.to_str().unwrap_or("");
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+fn not_modified(etag: HeaderValue, vary: bool) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    response.headers_mut().insert(header::ETAG, etag);
+    if vary {
+        response
+            .headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    response
+}
+
+/// 设置 `Cache-Control`：请求中写明了确切版本时内容不会再变化，
+/// 否则（版本范围、标签、分支）解析结果可能随发布而改变，只短时间缓存
+pub fn set_cache_control(response: &mut Response, immutable: bool) {
+    let value = if immutable {
+        IMMUTABLE_CACHE_CONTROL
+    } else {
+        FLOATING_CACHE_CONTROL
+    };
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+}
+
+/// 查找文件对应的 source map，返回相对于该文件的 URL
+///
+/// 优先使用文件末尾 `sourceMappingURL` 注释指向的包内文件，
+/// 否则退回到同目录下的 `{file}.map`。内联（data:）的 source map 不需要响应头。
+fn find_source_map(package_data: &PackageData, file_path: &str, content: &[u8]) -> Option<String> {
+    let extension = file_path.rsplit('.').next().unwrap_or("");
+    if !matches!(extension, "js" | "mjs" | "cjs" | "css") {
+        return None;
+    }
+
+    let dir = match file_path.rfind('/') {
+        Some(pos) => &file_path[..pos],
+        None => "",
+    };
+
+    if let Some(url) = source_mapping_url(content) {
+        if url.starts_with("data:") {
+            return None;
+        }
+        // 绝对 URL 原样返回，相对路径需确认包内存在
+        if url.contains("://") || url.starts_with('/') {
+            return Some(url.to_string());
+        }
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        if let Some(resolved) = join_relative(dir, path) {
+            if package_data.files.contains(&resolved) {
+                return Some(url.to_string());
+            }
+        }
+    }
+
+    let sibling = format!("{}.map", file_path);
+    if package_data.files.contains(&sibling) {
+        let file_name = file_path.rsplit('/').next().unwrap_or(file_path);
+        return Some(format!("{}.map", file_name));
+    }
+
+    None
+}
+
+/// 提取文件末尾的 `//# sourceMappingURL=` 或 `/*# sourceMappingURL= */` 注释
+fn source_mapping_url(content: &[u8]) -> Option<&str> {
+    // 注释只会出现在文件末尾，只检查最后一段内容
+    let tail = &content[content.len().saturating_sub(4096)..];
+    let tail = match std::str::from_utf8(tail) {
+        Ok(s) => s,
+        Err(e) => std::str::from_utf8(&tail[e.valid_up_to()..]).ok()?,
+    };
+
+    let line = tail.lines().rev().find(|l| !l.trim().is_empty())?.trim();
+    let rest = line
+        .strip_prefix("//# sourceMappingURL=")
+        .or_else(|| line.strip_prefix("//@ sourceMappingURL="))
+        .or_else(|| {
+            line.strip_prefix("/*# sourceMappingURL=")
+                .and_then(|r| r.strip_suffix("*/"))
+        })?
+        .trim();
+
+    if rest.is_empty() {
+        None
+    } else {
+        Some(rest)
+    }
+}
+
+/// 将相对路径拼接到包内目录上，越出包根目录时返回 None
+fn join_relative(dir: &str, relative: &str) -> Option<String> {
+    let mut segments: Vec<&str> = dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in relative.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            s => segments.push(s),
+        }
+    }
+    Some(segments.join("/"))
 }
 
 /// 返回目录列表
+///
+/// 目录在前、文件在后，各自按名称排序；文件显示大小、修改时间与 SRI 哈希。
+/// `versions` 为所有可用版本（从旧到新），用于版本切换。
+/// 客户端偏好 JSON 时（见 [`error::prefers_json`]）返回 JSON 格式的列表。
 pub fn directory_listing(
     package_data: &PackageData,
     dir_path: &str,
     package_name: &str,
     version: &str,
+    versions: &[String],
+    request_headers: &HeaderMap,
+    head: bool,
 ) -> Result<Response, AppError> {
     let prefix = if dir_path.is_empty() {
         String::new()
@@ -30,35 +354,73 @@ pub fn directory_listing(
         format!("{}/", dir_path)
     };
 
-    // 收集目录中的文件
-    let mut entries: Vec<(String, bool)> = package_data
+    // 收集目录中的直接子项，目录没有文件信息
+    let mut entries: Vec<(&str, Option<&FileInfo>)> = package_data
         .files
-        .keys()
+        .paths()
         .filter_map(|path| {
-            if path.starts_with(&prefix) {
-                let rest = &path[prefix.len()..];
-                if !rest.is_empty() {
-                    // 检查是否是直接子项
-                    if let Some(slash_pos) = rest.find('/') {
-                        // 是目录
-                        let dir_name = &rest[..slash_pos];
-                        Some((dir_name.to_string(), true))
-                    } else {
-                        // 是文件
-                        Some((rest.to_string(), false))
-                    }
-                } else {
-                    None
-                }
-            } else {
-                None
+            let rest = path.strip_prefix(&prefix)?;
+            match rest.find('/') {
+                Some(slash_pos) => Some((&rest[..slash_pos], None)),
+                None if !rest.is_empty() => Some((rest, package_data.files.info(path))),
+                None => None,
             }
         })
         .collect();
 
-    // 去重并排序
-    entries.sort();
-    entries.dedup();
+    // 目录在前，去重并排序
+    entries.sort_by(|a, b| a.1.is_some().cmp(&b.1.is_some()).then(a.0.cmp(b.0)));
+    entries.dedup_by(|a, b| a.0 == b.0 && a.1.is_none() && b.1.is_none());
+
+    let entry_file = package::resolve_entry_file(package_data)
+        .ok()
+        .filter(|entry| package_data.files.contains(entry));
+
+    if error::prefers_json(request_headers) {
+        let files: Vec<Value> = entries
+            .iter()
+            .map(|(name, info)| match info {
+                None => json!({"type": "directory", "name": name}),
+                Some(info) => json!({
+                    "type": "file",
+                    "name": name,
+                    "size": info.size,
+                    "mtime": info.mtime,
+                    "integrity": info.integrity.to_json(),
+                }),
+            })
+            .collect();
+        let body = json!({
+            "name": package_name,
+            "version": version,
+            "path": dir_path,
+            "entry": entry_file,
+            "files": files,
+            // 建立索引时被忽略或覆盖的条目
+            "anomalies": package_data
+                .files
+                .anomalies()
+                .iter()
+                .map(|anomaly| anomaly.to_json())
+                .collect::<Vec<_>>(),
+        });
+        return Ok(compress_dynamic(
+            body.to_string(),
+            "application/json",
+            request_headers,
+            head,
+        ));
+    }
+
+    let base = format!("/{}@{}/", html_escape(package_name), html_escape(version));
+    let dir_link = if dir_path.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", link_path(dir_path))
+    };
+    let package_name = html_escape(package_name);
+    let version = html_escape(version);
+    let title_path = html_escape(dir_path);
 
     // 生成 HTML
     let mut html = format!(
@@ -75,12 +437,21 @@ pub fn directory_listing(
         h1 {{
             color: #333;
         }}
-        ul {{
-            list-style: none;
-            padding: 0;
+        .meta {{
+            margin-bottom: 20px;
+        }}
+        .meta > * {{
+            margin-right: 20px;
+        }}
+        table {{
+            border-collapse: collapse;
         }}
-        li {{
-            margin: 5px 0;
+        th, td {{
+            padding: 4px 16px 4px 0;
+            text-align: left;
+        }}
+        td.size {{
+            text-align: right;
         }}
         a {{
             text-decoration: none;
@@ -99,51 +470,222 @@ pub fn directory_listing(
 </head>
 <body>
     <h1>Directory listing for {}@{}/{}</h1>
-    <ul>
+    <div class="meta">
+"#,
+        package_name, version, title_path, package_name, version, title_path
+    );
+
+    // 版本切换：保持当前目录，从新到旧排列
+    html.push_str(r#"        <label>Version <select onchange="location.href = this.value">"#);
+    html.push('\n');
+    let current = version.as_str();
+    let escaped_versions: Vec<String> = versions.iter().map(|v| html_escape(v)).collect();
+    let mut options: Vec<&str> = escaped_versions.iter().rev().map(String::as_str).collect();
+    if !options.contains(&current) {
+        options.insert(0, current);
+    }
+    for option in options {
+        html.push_str(&format!(
+            "            <option value=\"/{}@{}/{}\"{}>{}</option>\n",
+            package_name,
+            option,
+            dir_link,
+            if option == current { " selected" } else { "" },
+            option
+        ));
+    }
+    html.push_str("        </select></label>\n");
+
+    // 入口文件
+    if let Some(entry) = entry_file {
+        html.push_str(&format!(
+            "        <span>Entry file: <a href=\"{}{}\">{}</a></span>\n",
+            base,
+            link_path(&entry),
+            html_escape(&entry)
+        ));
+    }
+
+    html.push_str(
+        r#"    </div>
+    <table>
+        <thead>
+            <tr><th>Name</th><th>Size</th><th>Modified</th><th>SRI</th></tr>
+        </thead>
+        <tbody>
 "#,
-        package_name, version, dir_path, package_name, version, dir_path
     );
 
     // 添加父目录链接
     if !dir_path.is_empty() {
-        let parent = if let Some(pos) = dir_path.rfind('/') {
-            &dir_path[..pos]
-        } else {
-            ""
+        let parent = match dir_path.rfind('/') {
+            Some(pos) => format!("{}/", link_path(&dir_path[..pos])),
+            None => String::new(),
         };
         html.push_str(&format!(
-            r#"        <li><a href="/{}@{}/{}/" class="dir">../</a></li>
-"#,
-            package_name, version, parent
+            "            <tr><td><a href=\"{}{}\" class=\"dir\">../</a></td><td></td><td></td><td></td></tr>\n",
+            base, parent
         ));
     }
 
-    for (name, is_dir) in entries {
-        let class = if is_dir { "dir" } else { "file" };
-        let suffix = if is_dir { "/" } else { "" };
-        let link = if dir_path.is_empty() {
-            format!("/{}@{}/{}{}", package_name, version, name, suffix)
-        } else {
-            format!(
-                "/{}@{}/{}/{}{}",
-                package_name, version, dir_path, name, suffix
-            )
+    for (name, info) in entries {
+        let row = match info {
+            None => format!(
+                r#"<td><a href="{}{}{}/" class="dir">{}/</a></td><td></td><td></td><td></td>"#,
+                base,
+                dir_link,
+                link_path(name),
+                html_escape(name)
+            ),
+            Some(info) => format!(
+                r#"<td><a href="{}{}{}" class="file">{}</a></td><td class="size">{}</td><td>{}</td><td><button data-copy="{}" title="{}">Copy</button></td>"#,
+                base,
+                dir_link,
+                link_path(name),
+                html_escape(name),
+                format_size(info.size),
+                format_timestamp(info.mtime),
+                info.integrity.sha384(),
+                info.integrity
+            ),
         };
-
-        html.push_str(&format!(
-            r#"        <li><a href="{}" class="{}">{}{}</a></li>
-"#,
-            link, class, name, suffix
-        ));
+        html.push_str(&format!("            <tr>{}</tr>\n", row));
     }
 
     html.push_str(
-        r#"    </ul>
+        r#"        </tbody>
+    </table>
+    <script>
+        document.querySelectorAll("button[data-copy]").forEach(function (button) {
+            button.addEventListener("click", function () {
+                navigator.clipboard.writeText(button.dataset.copy).then(function () {
+                    button.textContent = "Copied";
+                });
+            });
+        });
+    </script>
 </body>
 </html>"#,
     );
 
-    Ok(Html(html).into_response())
+    Ok(compress_dynamic(
+        html,
+        "text/html; charset=utf-8",
+        request_headers,
+        head,
+    ))
+}
+
+/// 文件大小，例如 `512 B`、`1.5 KB`
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Unix 时间戳格式化为 UTC 时间 `YYYY-MM-DD HH:MM`
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    let (year, month, day) = date::civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+/// 转义 HTML 特殊字符（包名、文件名均来自不可信的包内容）
+fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 链接路径中需要百分号编码的字符（保留 `/` 作为分隔符）
+const PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}')
+    .add(b'\'')
+    .add(b'\\');
+
+/// 将包内路径编码为可以放入 href 属性的 URL 路径
+fn link_path(path: &str) -> String {
+    html_escape(&utf8_percent_encode(path, PATH_ENCODE_SET).to_string())
+}
+
+/// 压缩动态生成的页面（不缓存）
+fn compress_dynamic(
+    page: String,
+    content_type: &'static str,
+    request_headers: &HeaderMap,
+    head: bool,
+) -> Response {
+    // 同一地址按 Accept 返回 HTML 或 JSON
+    let vary = HeaderValue::from_static("accept-encoding, accept");
+    let hash = content_hash(page.as_bytes());
+    let encoding = compression::negotiate(request_headers)
+        .first()
+        .copied()
+        .filter(|_| page.len() >= compression::MIN_COMPRESS_SIZE);
+    if is_not_modified(request_headers, &etag(&hash, encoding)) {
+        let mut response = not_modified(etag(&hash, encoding), false);
+        response.headers_mut().insert(header::VARY, vary);
+        return response;
+    }
+
+    let compressed = encoding.and_then(|encoding| {
+        compression::compress_fast(page.as_bytes(), encoding)
+            .ok()
+            .map(|body| (body, encoding))
+    });
+
+    let (body, encoding) = match compressed {
+        Some((body, encoding)) => (Bytes::from(body), Some(encoding)),
+        None => (Bytes::from(page), None),
+    };
+    let mut response = body_response(body, head);
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(header::ETAG, etag(&hash, encoding));
+    if let Some(encoding) = encoding {
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+    }
+    headers.insert(header::VARY, vary);
+    response
 }
 
 /// 根据文件扩展名获取 Content-Type
@@ -174,3 +716,335 @@ fn get_content_type(file_path: &str) -> &'static str {
         _ => "application/octet-stream",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tarball, testutil};
+    use serde_json::json;
+
+    fn package(files: &[(&str, &str)]) -> PackageData {
+        let paths: Vec<_> = files
+            .iter()
+            .map(|(path, content)| (format!("package/{}", path), *content))
+            .collect();
+        let entries: Vec<_> = paths.iter().map(|(p, c)| (p.as_str(), *c)).collect();
+        PackageData {
+            files: tarball::Index::build(Bytes::from(testutil::tarball(&entries))).unwrap(),
+            package_json: json!({}),
+        }
+    }
+
+    fn read(data: &PackageData, path: &str) -> Bytes {
+        data.files.read(path).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_source_map_from_comment() {
+        let data = package(&[
+            (
+                "dist/app.min.js",
+                "console.log(1);\n//# sourceMappingURL=../maps/app.js.map\n",
+            ),
+            ("maps/app.js.map", "{}"),
+        ]);
+        let content = read(&data, "dist/app.min.js");
+        assert_eq!(
+            find_source_map(&data, "dist/app.min.js", &content),
+            Some("../maps/app.js.map".to_string())
+        );
+    }
+
+    #[test]
+    fn test_source_map_sibling_fallback() {
+        let data = package(&[
+            ("dist/style.css", "a{}\n/*# sourceMappingURL=missing.map */"),
+            ("dist/style.css.map", "{}"),
+            ("dist/plain.js", "1"),
+        ]);
+        let css = read(&data, "dist/style.css");
+        assert_eq!(
+            find_source_map(&data, "dist/style.css", &css),
+            Some("style.css.map".to_string())
+        );
+        assert_eq!(find_source_map(&data, "dist/plain.js", b"1"), None);
+    }
+
+    #[test]
+    fn test_source_map_inline_ignored() {
+        let data = package(&[
+            (
+                "index.js",
+                "x\n//# sourceMappingURL=data:application/json;base64,e30=",
+            ),
+            ("index.js.map", "{}"),
+        ]);
+        let content = read(&data, "index.js");
+        assert_eq!(find_source_map(&data, "index.js", &content), None);
+    }
+
+    #[test]
+    fn test_directory_listing_escapes_names() {
+        let data = package(&[("<img src=x onerror=alert(1)>.js", ""), ("a b/c.js", "")]);
+        let response = directory_listing(
+            &data,
+            "",
+            "evil<pkg>",
+            "1.0.0",
+            &[],
+            &HeaderMap::new(),
+            false,
+        )
+        .unwrap();
+        let html = body_string(response);
+
+        assert!(!html.contains("<img"));
+        assert!(!html.contains("evil<pkg>"));
+        assert!(html.contains("&lt;img src=x onerror=alert(1)&gt;.js"));
+        assert!(html.contains(r#"href="/evil&lt;pkg&gt;@1.0.0/a%20b/""#));
+    }
+
+    #[test]
+    fn test_directory_listing_details() {
+        let data = package(&[
+            ("index.js", "alert(1)"),
+            ("a.js", &"x".repeat(2048)),
+            ("zz/b.js", ""),
+            ("lib/c.js", ""),
+        ]);
+        let versions = vec![
+            "0.9.0".to_string(),
+            "1.0.0".to_string(),
+            "1.1.0".to_string(),
+        ];
+        let response = directory_listing(
+            &data,
+            "",
+            "demo",
+            "1.0.0",
+            &versions,
+            &HeaderMap::new(),
+            false,
+        )
+        .unwrap();
+        let html = body_string(response);
+
+        // 目录在前，各自按名称排序
+        let position = |name: &str| html.find(name).unwrap();
+        assert!(position(">lib/<") < position(">zz/<"));
+        assert!(position(">zz/<") < position(r#"class="file">a.js<"#));
+        assert!(position(r#"class="file">a.js<"#) < position(r#"class="file">index.js<"#));
+
+        assert!(html.contains("2.0 KB"));
+        assert!(html.contains("1970-01-01 00:00"));
+        let integrity = &data.files.info("index.js").unwrap().integrity;
+        assert!(html.contains(&format!(r#"data-copy="{}""#, integrity.sha384())));
+        assert!(html.contains(r#"Entry file: <a href="/demo@1.0.0/index.js">"#));
+
+        // 版本从新到旧，当前版本被选中
+        assert!(position(r#"value="/demo@1.1.0/""#) < position(r#"value="/demo@0.9.0/""#));
+        assert!(html.contains(r#"<option value="/demo@1.0.0/" selected>"#));
+    }
+
+    #[test]
+    fn test_directory_listing_json() {
+        let data = package(&[("index.js", "alert(1)"), ("lib/a.js", "")]);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        let response = directory_listing(&data, "", "demo", "1.0.0", &[], &headers, false).unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[header::VARY], "accept-encoding, accept");
+
+        let listing: Value = serde_json::from_str(&body_string(response)).unwrap();
+        assert_eq!(listing["entry"], "index.js");
+        assert_eq!(listing["anomalies"], json!([]));
+        assert_eq!(
+            listing["files"][0],
+            json!({"type": "directory", "name": "lib"})
+        );
+        let file = &listing["files"][1];
+        assert_eq!(file["name"], "index.js");
+        assert_eq!(file["size"], 8);
+        assert_eq!(
+            file["integrity"]["sha384"],
+            "sha384-HT2E9NfWiuQ/w1PRai+hTyqW16NIoCGA/m8VQDUopfAtcz6YQjtsMmQd5uRbVDpW"
+        );
+    }
+
+    #[test]
+    fn test_format_helpers() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MB");
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14 22:13");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00");
+    }
+
+    fn body_string(response: Response) -> String {
+        let bytes = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(axum::body::to_bytes(response.into_body(), usize::MAX))
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_head_and_conditional_requests() {
+        let script = "console.log('hello');\n".repeat(100);
+        let data = package(&[("index.js", &script)]);
+        let cache = CacheManager::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+
+        let get = file_response(
+            &data,
+            "index.js",
+            "package:demo@1.0.0",
+            &headers,
+            false,
+            &cache,
+        )
+        .await
+        .unwrap();
+        let head = file_response(
+            &data,
+            "index.js",
+            "package:demo@1.0.0",
+            &headers,
+            true,
+            &cache,
+        )
+        .await
+        .unwrap();
+        assert_eq!(get.headers(), head.headers());
+        assert_eq!(head.headers()[header::CONTENT_ENCODING], "gzip");
+        // SRI 哈希针对解压后的内容
+        let integrity = data.files.info("index.js").unwrap().integrity.to_string();
+        assert_eq!(head.headers()[&X_INTEGRITY], integrity.as_str());
+
+        let length: usize = head.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = axum::body::to_bytes(get.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), length);
+        let head_body = axum::body::to_bytes(head.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(head_body.is_empty());
+
+        // 同一 ETag 的条件请求返回 304，未压缩的表示使用另一个 ETag
+        let etag = get_etag(&data, &headers, &cache).await;
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let cached = file_response(
+            &data,
+            "index.js",
+            "package:demo@1.0.0",
+            &headers,
+            false,
+            &cache,
+        )
+        .await
+        .unwrap();
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(cached.headers()[header::ETAG], etag);
+
+        headers.remove(header::ACCEPT_ENCODING);
+        let identity = file_response(
+            &data,
+            "index.js",
+            "package:demo@1.0.0",
+            &headers,
+            false,
+            &cache,
+        )
+        .await
+        .unwrap();
+        assert_eq!(identity.status(), StatusCode::OK);
+        assert_ne!(identity.headers()[header::ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn test_extracted_files_are_cached() {
+        let data = package(&[("index.js", "console.log(1);")]);
+        let cache = CacheManager::new();
+        let get = || async {
+            let response = file_response(
+                &data,
+                "index.js",
+                "package:demo@1.0.0",
+                &HeaderMap::new(),
+                false,
+                &cache,
+            )
+            .await
+            .unwrap();
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        };
+
+        let first = get().await;
+        assert_eq!(first, &b"console.log(1);"[..]);
+        let cached = cache.get_file("package:demo@1.0.0/index.js").await.unwrap();
+        // 之后的请求直接引用缓存中的内容，不再解压也不复制
+        let second = get().await;
+        assert_eq!(second.as_ptr(), cached.as_ptr());
+    }
+
+    #[tokio::test]
+    async fn test_etag_from_index() {
+        let data = package(&[("index.js", "alert(1)")]);
+        let cache = CacheManager::new();
+        let etag = get_etag(&data, &HeaderMap::new(), &cache).await;
+        // 与文件内容的 sha256 一致，不需要每次请求重新计算
+        assert_eq!(etag, "\"6e11c72f7cf6bc383152dd16ddd5903a\"");
+
+        // 条件请求命中时不解压文件
+        let cache = CacheManager::new();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag);
+        let response = file_response(
+            &data,
+            "index.js",
+            "package:demo@1.0.0",
+            &headers,
+            false,
+            &cache,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(cache
+            .get_file("package:demo@1.0.0/index.js")
+            .await
+            .is_none());
+    }
+
+    async fn get_etag(
+        data: &PackageData,
+        headers: &HeaderMap,
+        cache: &CacheManager,
+    ) -> HeaderValue {
+        file_response(data, "index.js", "package:demo@1.0.0", headers, true, cache)
+            .await
+            .unwrap()
+            .headers()[header::ETAG]
+            .clone()
+    }
+
+    #[test]
+    fn test_join_relative() {
+        assert_eq!(
+            join_relative("a/b", "../c.map"),
+            Some("a/c.map".to_string())
+        );
+        assert_eq!(join_relative("", "./c.map"), Some("c.map".to_string()));
+        assert_eq!(join_relative("", "../c.map"), None);
+    }
+}
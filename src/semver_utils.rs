@@ -1,66 +1,234 @@
+use crate::date::{format_timestamp, parse_timestamp};
 use crate::error::AppError;
+use node_semver::{Range, Version};
 use serde_json::Value;
 
-/// 解析版本（支持语义化版本）
+/// 解析版本，规则与 npm 安装时选择版本（npm-pick-manifest）一致：
+///
+/// 1. 未指定版本时使用 `latest` 标签
+/// 2. dist-tag（如 `next`）直接使用标签指向的版本
+/// 3. 确切版本（允许 `v` / `=` 前缀）必须存在
+/// 4. 部分版本（`3`、`3.3`）选择该主版本 / 次版本中最高的正式版本
+/// 5. 版本范围（包括 `1.x`、`*` 这类写法）：
+///    `latest` 满足范围且未被标记为 deprecated 时优先使用 `latest`，否则选择满足范围的
+///    最高版本，未被标记为 deprecated 的版本优先。预发布版本只有在范围中同一
+///    `主.次.修订` 版本带有预发布标识时才会被选中（`*` 直接使用 `latest`）
+#[tracing::instrument(skip(metadata, version_str), fields(range = version_str))]
 pub fn resolve_version(metadata: &Value, version_str: Option<&str>) -> Result<String, AppError> {
-    match version_str {
-        None => {
-            // 使用 latest 标签
-            metadata
-                .get("dist-tags")
-                .and_then(|tags| tags.get("latest"))
-                .and_then(|v| v.as_str())
+    select(metadata, version_str, None)
+}
+
+/// 按 [`resolve_version`] 的规则解析版本，但只考虑 `before`（Unix 毫秒）之前发布的版本，
+/// 即该版本说明在当时会解析到的结果
+///
+/// 发布时间取自 packument 的 `time` 字段，没有发布时间的版本被跳过。
+/// dist-tag 只记录当前指向的版本：指向的版本在当时已经发布才会使用，
+/// 未指定版本或 `latest` 在当时尚未发布时退回到 `*`。
+#[tracing::instrument(skip(metadata, version_str), fields(range = version_str))]
+pub fn resolve_version_before(
+    metadata: &Value,
+    version_str: Option<&str>,
+    before: i64,
+) -> Result<String, AppError> {
+    select(metadata, version_str, Some(before))
+}
+
+fn select(
+    metadata: &Value,
+    version_str: Option<&str>,
+    before: Option<i64>,
+) -> Result<String, AppError> {
+    let published = |key: &str| match before {
+        None => true,
+        Some(before) => published_at(metadata, key).is_some_and(|time| time < before),
+    };
+    let latest = metadata
+        .get("dist-tags")
+        .and_then(|tags| tags.get("latest"))
+        .and_then(|v| v.as_str())
+        .filter(|latest| published(latest));
+
+    let spec = match version_str.map(str::trim) {
+        None | Some("") if before.is_none() => {
+            return latest
                 .map(|s| s.to_string())
-                .ok_or_else(|| AppError::NotFound("No latest version found".to_string()))
+                .ok_or_else(|| AppError::VersionNotFound("No latest version found".to_string()));
         }
-        Some(v) => {
-            // 检查是否是 dist-tags
-            if let Some(tags) = metadata.get("dist-tags") {
-                if let Some(tag_version) = tags.get(v).and_then(|v| v.as_str()) {
-                    return Ok(tag_version.to_string());
-                }
-            }
-
-            // 检查是否是精确版本
-            if metadata
-                .get("versions")
-                .and_then(|versions| versions.get(v))
-                .is_some()
-            {
-                return Ok(v.to_string());
-            }
+        // 按日期解析时 `latest` 可能还没有发布，按 `*` 处理
+        None | Some("") => "*",
+        Some(spec) => spec,
+    };
+    let not_found = || match before {
+        None => AppError::VersionNotFound(format!("No matching version found for '{}'", spec)),
+        Some(before) => AppError::VersionNotFound(format!(
+            "No matching version found for '{}' published before {}",
+            spec,
+            format_timestamp(before)
+        )),
+    };
 
-            // 尝试解析语义化版本范围
-            match parse_semver_range(metadata, v) {
-                Some(version) => Ok(version),
-                None => Err(AppError::NotFound(format!(
-                    "No matching version found for '{}'",
-                    v
-                ))),
-            }
+    // dist-tag
+    if let Some(tag_version) = metadata
+        .get("dist-tags")
+        .and_then(|tags| tags.get(spec))
+        .and_then(|v| v.as_str())
+    {
+        if published(tag_version) {
+            return Ok(tag_version.to_string());
+        }
+        if spec != "latest" {
+            return Err(not_found());
         }
     }
-}
+    let spec = if spec == "latest" { "*" } else { spec };
 
-/// 解析语义化版本范围
-fn parse_semver_range(metadata: &Value, range_str: &str) -> Option<String> {
-    use node_semver::{Range, Version};
+    let versions: Vec<_> = parsed_versions(metadata)
+        .into_iter()
+        .filter(|(_, key)| published(key))
+        .collect();
 
-    let versions = metadata.get("versions")?.as_object()?;
+    // 确切版本：与 registry 中的写法完全一致，或解析后相等
+    if metadata
+        .get("versions")
+        .and_then(|versions| versions.get(spec))
+        .is_some()
+    {
+        return if published(spec) {
+            Ok(spec.to_string())
+        } else {
+            Err(not_found())
+        };
+    }
+    if let Some(exact) = parse_exact(spec) {
+        return versions
+            .iter()
+            .find(|(version, _)| *version == exact)
+            .map(|(_, key)| key.to_string())
+            .ok_or_else(not_found);
+    }
 
-    // 解析范围
-    let range = Range::parse(range_str).ok()?;
+    let partial = parse_partial(spec);
+    let range = match &partial {
+        Some(range) => range.clone(),
+        None => Range::parse(spec).map_err(|_| not_found())?,
+    };
 
-    // 收集所有符合条件的版本
-    let mut matching_versions: Vec<Version> = versions
+    // `latest` 满足范围且未废弃时优先使用，部分版本总是取最高版本
+    if let (Some(latest), None) = (latest.filter(|l| !is_deprecated(metadata, l)), &partial) {
+        let satisfied = matches!(spec, "*" | "x" | "X")
+            || Version::parse(latest).is_ok_and(|v| range.satisfies(&v));
+        if satisfied && versions.iter().any(|(_, key)| *key == latest) {
+            return Ok(latest.to_string());
+        }
+    }
+
+    versions
+        .iter()
+        .filter(|(version, _)| range.satisfies(version))
+        .max_by(|(a, a_key), (b, b_key)| {
+            // 未废弃的版本优先，其次取最高版本
+            let a_ok = !is_deprecated(metadata, a_key);
+            let b_ok = !is_deprecated(metadata, b_key);
+            a_ok.cmp(&b_ok).then_with(|| a.cmp(b))
+        })
+        .map(|(_, key)| key.to_string())
+        .ok_or_else(not_found)
+}
+
+/// 按语义化版本从旧到新列出所有版本（无法解析的版本排在最前面）
+pub fn sorted_versions(metadata: &Value) -> Vec<String> {
+    let Some(versions) = metadata.get("versions").and_then(|v| v.as_object()) else {
+        return Vec::new();
+    };
+
+    let mut versions: Vec<(Option<Version>, &String)> = versions
         .keys()
-        .filter_map(|v| Version::parse(v).ok())
-        .filter(|v| range.satisfies(v))
+        .map(|v| (Version::parse(v).ok(), v))
         .collect();
+    versions.sort();
+    versions.into_iter().map(|(_, v)| v.clone()).collect()
+}
 
-    // 排序并返回最新版本
-    matching_versions.sort();
-    matching_versions.last().map(|v| v.to_string())
+/// 可以解析的版本及其在 registry 中的原始写法，无法解析的版本被跳过
+fn parsed_versions(metadata: &Value) -> Vec<(Version, &str)> {
+    let Some(versions) = metadata.get("versions").and_then(|v| v.as_object()) else {
+        return Vec::new();
+    };
+    versions
+        .keys()
+        .filter_map(|key| match Version::parse(key) {
+            Ok(version) => Some((version, key.as_str())),
+            Err(_) => {
+                tracing::debug!("Skipping invalid version {:?}", key);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 确切版本（`1.2.3`、`v1.2.3`、`=1.2.3`），部分版本与范围返回 None
+fn parse_exact(spec: &str) -> Option<Version> {
+    let spec = spec.strip_prefix('=').unwrap_or(spec).trim_start();
+    let spec = spec.strip_prefix('v').unwrap_or(spec);
+    // Version::parse 会接受 `1.2.3beta` 这类宽松写法，这里只认三段式数字开头的版本
+    let core = spec.split(['-', '+']).next().unwrap_or("");
+    let is_exact = core.split('.').count() == 3
+        && core
+            .split('.')
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+    if is_exact {
+        Version::parse(spec).ok()
+    } else {
+        None
+    }
+}
+
+/// 部分版本（`3`、`3.3`，允许 `v` 前缀），对应 `>=3.0.0 <4.0.0-0` 这样的范围
+fn parse_partial(spec: &str) -> Option<Range> {
+    let spec = spec.strip_prefix('v').unwrap_or(spec);
+    let parts: Vec<u64> = spec
+        .split('.')
+        .map(|part| {
+            if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) {
+                part.parse().ok()
+            } else {
+                None
+            }
+        })
+        .collect::<Option<_>>()?;
+    let range = match parts[..] {
+        [major] => format!(">={}.0.0 <{}.0.0-0", major, major.checked_add(1)?),
+        [major, minor] => format!(
+            ">={}.{}.0 <{}.{}.0-0",
+            major,
+            minor,
+            major,
+            minor.checked_add(1)?
+        ),
+        _ => return None,
+    };
+    Range::parse(range).ok()
+}
+
+/// 版本在 packument `time` 字段中记录的发布时间（Unix 毫秒）
+fn published_at(metadata: &Value, version: &str) -> Option<i64> {
+    metadata
+        .get("time")
+        .and_then(|time| time.get(version))
+        .and_then(|v| v.as_str())
+        .and_then(parse_timestamp)
+}
+
+fn is_deprecated(metadata: &Value, version: &str) -> bool {
+    metadata
+        .get("versions")
+        .and_then(|versions| versions.get(version))
+        .and_then(|manifest| manifest.get("deprecated"))
+        .is_some_and(|deprecated| match deprecated {
+            Value::String(message) => !message.is_empty(),
+            Value::Bool(deprecated) => *deprecated,
+            _ => false,
+        })
 }
 
 #[cfg(test)]
@@ -99,4 +267,248 @@ mod tests {
         // 测试范围
         assert_eq!(resolve_version(&metadata, Some("^1.0.0")).unwrap(), "1.2.3");
     }
+
+    /// 由版本列表与 dist-tags 构造 packument
+    fn packument(versions: &[&str], tags: Value) -> Value {
+        let versions: serde_json::Map<String, Value> = versions
+            .iter()
+            .map(|v| (v.to_string(), json!({"version": v})))
+            .collect();
+        json!({"dist-tags": tags, "versions": versions})
+    }
+
+    fn pick(metadata: &Value, spec: &str) -> Option<String> {
+        resolve_version(metadata, Some(spec)).ok()
+    }
+
+    // 以下用例对照 npm-pick-manifest 的测试
+
+    #[test]
+    fn test_basic_selection() {
+        let metadata = packument(&["1.0.0", "1.0.1", "1.0.2", "2.0.0"], json!({}));
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.0.2");
+        assert_eq!(pick(&metadata, "~1.0.0").unwrap(), "1.0.2");
+        assert_eq!(pick(&metadata, "<1.0.2").unwrap(), "1.0.1");
+        assert_eq!(pick(&metadata, ">=1.0.0 <2").unwrap(), "1.0.2");
+        assert_eq!(pick(&metadata, "1.0.0").unwrap(), "1.0.0");
+        assert_eq!(pick(&metadata, "^1.0.0 || ^2.0.0").unwrap(), "2.0.0");
+        assert!(pick(&metadata, "^2.1.0").is_none());
+        assert!(pick(&metadata, "1.0.3").is_none());
+    }
+
+    #[test]
+    fn test_tag_selection() {
+        let metadata = packument(
+            &["1.0.0", "1.0.1", "2.0.0"],
+            json!({"latest": "1.0.0", "foo": "1.0.1"}),
+        );
+        assert_eq!(pick(&metadata, "foo").unwrap(), "1.0.1");
+        assert!(pick(&metadata, "bar").is_none());
+    }
+
+    #[test]
+    fn test_prefers_latest_when_it_satisfies() {
+        let metadata = packument(
+            &["1.0.0", "1.0.1", "1.0.2", "2.0.0"],
+            json!({"latest": "1.0.1"}),
+        );
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.0.1");
+        assert_eq!(pick(&metadata, "*").unwrap(), "1.0.1");
+        assert_eq!(pick(&metadata, "x").unwrap(), "1.0.1");
+        // 不满足时选择最高版本
+        assert_eq!(pick(&metadata, "^2.0.0").unwrap(), "2.0.0");
+        assert_eq!(pick(&metadata, "~1.0.2").unwrap(), "1.0.2");
+    }
+
+    #[test]
+    fn test_star_uses_prerelease_latest() {
+        let metadata = packument(
+            &["1.0.0-pre", "2.0.0-beta.0"],
+            json!({"latest": "2.0.0-beta.0"}),
+        );
+        assert_eq!(pick(&metadata, "*").unwrap(), "2.0.0-beta.0");
+        assert_eq!(
+            resolve_version(&metadata, Some("")).unwrap(),
+            "2.0.0-beta.0"
+        );
+
+        // 没有 latest 时范围不会选中预发布版本
+        let metadata = packument(&["1.0.0-pre", "2.0.0-beta.0"], json!({}));
+        assert!(pick(&metadata, "*").is_none());
+    }
+
+    #[test]
+    fn test_excludes_prereleases() {
+        let metadata = packument(
+            &[
+                "1.0.0",
+                "1.1.0-beta.1",
+                "1.1.0",
+                "1.2.0-beta.1",
+                "2.0.0-rc.1",
+                "3.0.0",
+            ],
+            json!({"latest": "3.0.0", "next": "2.0.0-rc.1"}),
+        );
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.1.0");
+        assert_eq!(pick(&metadata, "1").unwrap(), "1.1.0");
+        assert_eq!(pick(&metadata, ">=1.1.0 <3").unwrap(), "1.1.0");
+        assert!(pick(&metadata, "^2.0.0").is_none());
+        // 范围在同一版本上带预发布标识时可以选中
+        assert_eq!(pick(&metadata, "^1.2.0-beta.0").unwrap(), "1.2.0-beta.1");
+        assert_eq!(pick(&metadata, "^2.0.0-rc.0").unwrap(), "2.0.0-rc.1");
+        assert_eq!(pick(&metadata, "~1.1.0-beta.0").unwrap(), "1.1.0");
+        // latest 是预发布版本时同样受此限制
+        let metadata = packument(
+            &["1.0.0", "1.1.0-beta.1"],
+            json!({"latest": "1.1.0-beta.1"}),
+        );
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.0.0");
+    }
+
+    #[test]
+    fn test_partial_and_loose_versions() {
+        let metadata = packument(
+            &["1.2.0", "1.2.9", "1.3.0", "2.0.0"],
+            json!({"latest": "2.0.0"}),
+        );
+        assert_eq!(pick(&metadata, "1").unwrap(), "1.3.0");
+        assert_eq!(pick(&metadata, "1.2").unwrap(), "1.2.9");
+        assert_eq!(pick(&metadata, "1.x").unwrap(), "1.3.0");
+        assert_eq!(pick(&metadata, "1.2.*").unwrap(), "1.2.9");
+        assert_eq!(pick(&metadata, "v1.2.0").unwrap(), "1.2.0");
+        assert_eq!(pick(&metadata, "=1.2.0").unwrap(), "1.2.0");
+        assert_eq!(pick(&metadata, "^v1.2").unwrap(), "1.3.0");
+        assert_eq!(pick(&metadata, " ^1.2.0 ").unwrap(), "1.3.0");
+        assert!(pick(&metadata, "v1.2.1").is_none());
+        assert!(pick(&metadata, "not a range").is_none());
+    }
+
+    #[test]
+    fn test_skips_invalid_versions() {
+        let metadata = packument(&["1.0.0", "lol", "1.0.1"], json!({}));
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.0.1");
+        // 写法不规范的版本返回 registry 中的原始键
+        let metadata = packument(&["1.0.0", "v1.0.1"], json!({}));
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "v1.0.1");
+        assert_eq!(pick(&metadata, "1.0.1").unwrap(), "v1.0.1");
+    }
+
+    #[test]
+    fn test_prefers_non_deprecated() {
+        let mut metadata = packument(
+            &["1.0.0", "1.0.1", "1.0.2", "2.0.0"],
+            json!({"latest": "2.0.0"}),
+        );
+        metadata["versions"]["1.0.2"]["deprecated"] = json!("broken");
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.0.1");
+
+        // 全部废弃时仍选择最高版本，确切版本不受影响
+        metadata["versions"]["1.0.0"]["deprecated"] = json!("old");
+        metadata["versions"]["1.0.1"]["deprecated"] = json!("old");
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.0.2");
+        assert_eq!(pick(&metadata, "1.0.1").unwrap(), "1.0.1");
+    }
+
+    #[test]
+    fn test_skips_deprecated_latest() {
+        let mut metadata = packument(
+            &["1.0.0", "1.1.0", "1.2.0", "2.0.0-beta.1"],
+            json!({"latest": "1.2.0", "next": "2.0.0-beta.1"}),
+        );
+        metadata["versions"]["1.2.0"]["deprecated"] = json!("broken release");
+        assert_eq!(pick(&metadata, "^1.0.0").unwrap(), "1.1.0");
+        assert_eq!(pick(&metadata, "*").unwrap(), "1.1.0");
+        assert_eq!(pick(&metadata, "1.x").unwrap(), "1.1.0");
+
+        // 明确指定标签或确切版本时仍然使用
+        assert_eq!(resolve_version(&metadata, None).unwrap(), "1.2.0");
+        assert_eq!(pick(&metadata, "latest").unwrap(), "1.2.0");
+        assert_eq!(pick(&metadata, "1.2.0").unwrap(), "1.2.0");
+
+        // 没有其他满足范围的版本时仍然选择 latest
+        assert_eq!(pick(&metadata, "~1.2.0").unwrap(), "1.2.0");
+    }
+
+    #[test]
+    fn test_partial_versions_pick_highest() {
+        // latest 被回退到较旧版本时，`@3` 仍然指向 3.x 中最高的正式版本
+        let metadata = packument(
+            &["3.2.0", "3.3.0", "3.3.4", "3.4.0-beta.1", "4.0.0"],
+            json!({"latest": "3.2.0"}),
+        );
+        assert_eq!(pick(&metadata, "3").unwrap(), "3.3.4");
+        assert_eq!(pick(&metadata, "3.3").unwrap(), "3.3.4");
+        assert_eq!(pick(&metadata, "v3.2").unwrap(), "3.2.0");
+        assert!(pick(&metadata, "3.5").is_none());
+        assert!(pick(&metadata, "5").is_none());
+        // 范围写法仍然优先使用 latest
+        assert_eq!(pick(&metadata, "^3.0.0").unwrap(), "3.2.0");
+    }
+
+    #[test]
+    fn test_resolve_before_date() {
+        let mut metadata = packument(
+            &["1.0.0", "1.1.0", "2.0.0-beta.1", "2.0.0", "2.1.0"],
+            json!({"latest": "2.1.0", "beta": "2.0.0-beta.1"}),
+        );
+        metadata["time"] = json!({
+            "created": "2022-01-01T00:00:00.000Z",
+            "1.0.0": "2022-01-01T00:00:00.000Z",
+            "1.1.0": "2022-06-01T00:00:00.000Z",
+            "2.0.0-beta.1": "2023-03-01T00:00:00.000Z",
+            "2.0.0": "2023-06-01T00:00:00.000Z",
+            "2.1.0": "2024-02-01T00:00:00.000Z",
+        });
+        let before = |metadata: &Value, spec: Option<&str>, date: &str| {
+            resolve_version_before(metadata, spec, parse_timestamp(date).unwrap()).ok()
+        };
+
+        assert_eq!(
+            before(&metadata, Some("^2"), "2024-01-01").unwrap(),
+            "2.0.0"
+        );
+        assert_eq!(
+            before(&metadata, Some("^2"), "2025-01-01").unwrap(),
+            "2.1.0"
+        );
+        assert_eq!(before(&metadata, Some("2"), "2024-01-01").unwrap(), "2.0.0");
+        assert_eq!(
+            before(&metadata, Some("^1.0.0"), "2022-03-01").unwrap(),
+            "1.0.0"
+        );
+        assert!(before(&metadata, Some("^2"), "2023-01-01").is_none());
+        // 未指定版本与 latest：latest 当时尚未发布时使用当时最高的正式版本
+        assert_eq!(before(&metadata, None, "2023-05-01").unwrap(), "1.1.0");
+        assert_eq!(
+            before(&metadata, Some("latest"), "2024-01-01").unwrap(),
+            "2.0.0"
+        );
+        assert_eq!(before(&metadata, None, "2025-01-01").unwrap(), "2.1.0");
+        // 其他标签与确切版本必须在当时已经发布
+        assert_eq!(
+            before(&metadata, Some("beta"), "2024-01-01").unwrap(),
+            "2.0.0-beta.1"
+        );
+        assert!(before(&metadata, Some("beta"), "2023-01-01").is_none());
+        assert!(before(&metadata, Some("2.1.0"), "2024-01-01").is_none());
+        assert_eq!(
+            before(&metadata, Some("2.0.0"), "2024-01-01").unwrap(),
+            "2.0.0"
+        );
+        // 恰好在该时间发布的版本不算
+        assert_eq!(
+            before(&metadata, Some("^1"), "2022-06-01").unwrap(),
+            "1.0.0"
+        );
+        assert!(before(&metadata, None, "2021-01-01").is_none());
+
+        // 没有发布时间的版本被跳过
+        metadata["time"].as_object_mut().unwrap().remove("2.0.0");
+        assert!(before(&metadata, Some("^2"), "2024-01-01").is_none());
+        assert!(matches!(
+            resolve_version_before(&metadata, Some("^2"), 0),
+            Err(AppError::VersionNotFound(msg)) if msg.ends_with("before 1970-01-01T00:00:00.000Z")
+        ));
+    }
 }
@@ -0,0 +1,248 @@
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// 停机配置
+#[derive(Debug, Clone)]
+pub struct ShutdownConfig {
+    /// 收到信号后先报告未就绪、继续接收请求的时长，留给负载均衡器摘除实例
+    pub delay: Duration,
+    /// 停止接收新连接后，等待进行中的请求完成的最长时间
+    pub drain_timeout: Duration,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            delay: Duration::ZERO,
+            drain_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ShutdownConfig {
+    /// 从 `SHUTDOWN_DELAY` / `SHUTDOWN_TIMEOUT` 环境变量读取（秒）
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let seconds = |name: &str, default: Duration| {
+            std::env::var(name)
+                .map(|v| {
+                    Duration::from_secs_f64(
+                        v.parse()
+                            .unwrap_or_else(|_| panic!("{} must be a number of seconds", name)),
+                    )
+                })
+                .unwrap_or(default)
+        };
+
+        Self {
+            delay: seconds("SHUTDOWN_DELAY", defaults.delay),
+            drain_timeout: seconds("SHUTDOWN_TIMEOUT", defaults.drain_timeout),
+        }
+    }
+}
+
+/// 就绪状态，收到停机信号后变为未就绪
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn is_draining(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn start_draining(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 健康检查路由：`/-/health` 只要进程存活就返回 200，
+/// `/-/ready` 在停机过程中返回 503
+pub fn router(readiness: Readiness) -> Router {
+    Router::new()
+        .route("/-/health", get(|| async { "ok" }))
+        .route("/-/ready", get(ready_handler))
+        .with_state(readiness)
+}
+
+async fn ready_handler(State(readiness): State<Readiness>) -> (StatusCode, &'static str) {
+    if readiness.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "ok")
+    }
+}
+
+/// 等待 SIGTERM 或 SIGINT（Ctrl+C）
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received SIGINT"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+/// 运行服务直到 `signal` 完成，然后优雅停机
+///
+/// 依次：标记未就绪并等待 `delay`；停止接收新连接，等待进行中的请求
+/// （包括未传输完的文件）完成；超过 `drain_timeout` 仍未完成时放弃等待。
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    readiness: Readiness,
+    config: ShutdownConfig,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    // 限流需要客户端地址
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async {
+        let _ = stop_rx.await;
+    });
+    let mut server = tokio::spawn(async move { server.await });
+
+    tokio::select! {
+        result = &mut server => return result.expect("Server task panicked"),
+        _ = signal => {}
+    }
+
+    readiness.start_draining();
+    if !config.delay.is_zero() {
+        tracing::info!("Reporting not ready, shutting down in {:?}", config.delay);
+        tokio::time::sleep(config.delay).await;
+    }
+
+    tracing::info!(
+        "Draining in-flight requests (timeout {:?})",
+        config.drain_timeout
+    );
+    let _ = stop_tx.send(());
+    match tokio::time::timeout(config.drain_timeout, &mut server).await {
+        Ok(result) => {
+            tracing::info!("Server stopped");
+            result.expect("Server task panicked")
+        }
+        Err(_) => {
+            tracing::warn!("Drain timeout exceeded, dropping remaining connections");
+            server.abort();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    async fn start(
+        config: ShutdownConfig,
+    ) -> (
+        String,
+        Readiness,
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<std::io::Result<()>>,
+    ) {
+        let readiness = Readiness::default();
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    "done"
+                }),
+            )
+            .route("/hang", get(std::future::pending::<()>))
+            .merge(router(readiness.clone()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, app, readiness.clone(), config, async {
+            let _ = signal_rx.await;
+        }));
+        (base, readiness, signal_tx, server)
+    }
+
+    #[tokio::test]
+    async fn test_drains_in_flight_requests() {
+        let (base, readiness, signal, server) = start(ShutdownConfig {
+            delay: Duration::from_millis(200),
+            drain_timeout: Duration::from_secs(5),
+        })
+        .await;
+        let client = reqwest::Client::new();
+
+        let ready = client
+            .get(format!("{}/-/ready", base))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(ready.status(), 200);
+
+        let slow = tokio::spawn(client.get(format!("{}/slow", base)).send());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        signal.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // 停机等待期间仍然接收请求，但报告未就绪
+        assert!(readiness.is_draining());
+        let ready = client
+            .get(format!("{}/-/ready", base))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(ready.status(), 503);
+        let health = client
+            .get(format!("{}/-/health", base))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(health.status(), 200);
+
+        // 进行中的请求完整返回
+        let slow = slow.await.unwrap().unwrap();
+        assert_eq!(slow.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout() {
+        let (base, _, signal, server) = start(ShutdownConfig {
+            delay: Duration::ZERO,
+            drain_timeout: Duration::from_millis(200),
+        })
+        .await;
+
+        let hang = tokio::spawn(reqwest::get(format!("{}/hang", base)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let start = Instant::now();
+        signal.send(()).unwrap();
+
+        server.await.unwrap().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        hang.abort();
+    }
+}
@@ -0,0 +1,305 @@
+use crate::cache::PackageData;
+use crate::error::AppError;
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::Arc;
+
+/// 包来源（npm registry、git 仓库等）
+///
+/// 请求处理流程只依赖这个 trait：解析路径 → 解析版本 → 获取文件树。
+#[async_trait]
+pub trait PackageSource: Send + Sync {
+    /// 解析 URL 前缀之后的路径为 (包名, 版本说明, 文件路径)
+    ///
+    /// 路径未经百分号解码，由实现拆分后逐段解码。
+    fn parse_path(&self, path: &str) -> Result<(String, Option<String>, Option<String>), AppError>;
+
+    /// 将版本说明（范围、tag 等）解析为精确版本，未指定时使用默认版本
+    async fn resolve_version(&self, name: &str, spec: Option<&str>) -> Result<String, AppError>;
+
+    /// 按版本说明在 `before`（Unix 毫秒）时会解析到的版本，需要来源记录各版本的发布时间
+    async fn resolve_version_before(
+        &self,
+        name: &str,
+        spec: Option<&str>,
+        before: i64,
+    ) -> Result<String, AppError> {
+        let _ = (name, spec, before);
+        Err(AppError::InvalidRequest(
+            "Resolving versions by date is not supported for this source".to_string(),
+        ))
+    }
+
+    /// 列出所有可用版本（从旧到新）
+    async fn list_versions(&self, name: &str) -> Result<Vec<String>, AppError>;
+
+    /// 获取指定精确版本的文件树
+    async fn fetch_package(&self, name: &str, version: &str) -> Result<Arc<PackageData>, AppError>;
+
+    /// 该来源在 CacheManager 中存放元信息（版本列表、引用等）使用的键
+    fn metadata_cache_key(&self, name: &str) -> String;
+
+    /// 该来源在 CacheManager 中存放指定版本文件树使用的键
+    fn package_cache_key(&self, name: &str, version: &str) -> String;
+}
+
+/// 按 URL 前缀选择包来源
+pub struct SourceRegistry {
+    /// (前缀, 来源)，前缀形如 `npm/`
+    sources: Vec<(String, Arc<dyn PackageSource>)>,
+    /// 没有匹配前缀时使用的来源
+    default: Arc<dyn PackageSource>,
+}
+
+impl SourceRegistry {
+    pub fn new(default: Arc<dyn PackageSource>) -> Self {
+        Self {
+            sources: Vec::new(),
+            default,
+        }
+    }
+
+    /// 注册来源，`prefix` 不含斜杠（例如 `npm`）
+    pub fn register(&mut self, prefix: &str, source: Arc<dyn PackageSource>) {
+        self.sources.push((format!("{}/", prefix), source));
+    }
+
+    /// 按前缀（不含斜杠）查找已注册的来源
+    pub fn get(&self, prefix: &str) -> Option<&Arc<dyn PackageSource>> {
+        self.sources
+            .iter()
+            .find(|(registered, _)| registered.strip_suffix('/') == Some(prefix))
+            .map(|(_, source)| source)
+    }
+
+    /// 根据路径选择来源，返回 (前缀, 来源, 去掉前缀后的路径)
+    pub fn route<'a>(&self, path: &'a str) -> (&str, &Arc<dyn PackageSource>, &'a str) {
+        let path = path.trim_start_matches('/');
+        for (prefix, source) in &self.sources {
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                return (prefix, source, rest);
+            }
+        }
+        ("", &self.default, path)
+    }
+
+    /// 选择来源并解析路径后交给 `attempt` 处理
+    ///
+    /// 前缀与 npm 包名可能重名（npm 上有名为 `gh`、`local` 的包）：前缀来源无法解析路径时，
+    /// 改由默认来源按完整路径处理（`/gh/package.json` 即 `gh` 包中的 `package.json`）。
+    /// 路径能被前缀来源解析时结果以该来源为准，包不存在时同样返回它的 `PackageNotFound`，
+    /// 不会再访问默认来源；前缀来源与默认来源相同（`npm/`）时也不会退回。
+    pub async fn resolve_with_fallback<'a, T, Fut>(
+        &'a self,
+        path: &str,
+        mut attempt: impl FnMut(
+            &'a str,
+            &'a Arc<dyn PackageSource>,
+            (String, Option<String>, Option<String>),
+        ) -> Fut,
+    ) -> Result<T, AppError>
+    where
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        let (prefix, source, rest) = self.route(path);
+        let error = match source.parse_path(rest) {
+            Ok(parsed) => return attempt(prefix, source, parsed).await,
+            Err(err) if prefix.is_empty() || Arc::ptr_eq(source, &self.default) => return Err(err),
+            Err(err) => err,
+        };
+
+        let path = path.trim_start_matches('/');
+        let Ok(parsed) = self.default.parse_path(path) else {
+            return Err(error);
+        };
+        tracing::debug!("Falling back to the default source for {}", path);
+        attempt("", &self.default, parsed).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named(&'static str);
+
+    #[async_trait]
+    impl PackageSource for Named {
+        fn parse_path(
+            &self,
+            _path: &str,
+        ) -> Result<(String, Option<String>, Option<String>), AppError> {
+            Ok((self.0.to_string(), None, None))
+        }
+
+        async fn resolve_version(&self, _: &str, _: Option<&str>) -> Result<String, AppError> {
+            Ok(self.0.to_string())
+        }
+
+        async fn list_versions(&self, _: &str) -> Result<Vec<String>, AppError> {
+            Ok(Vec::new())
+        }
+
+        async fn fetch_package(&self, _: &str, _: &str) -> Result<Arc<PackageData>, AppError> {
+            Err(AppError::NotFound(self.0.to_string()))
+        }
+
+        fn metadata_cache_key(&self, name: &str) -> String {
+            format!("metadata:{}/{}", self.0, name)
+        }
+
+        fn package_cache_key(&self, name: &str, version: &str) -> String {
+            format!("package:{}/{}@{}", self.0, name, version)
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let mut registry = SourceRegistry::new(Arc::new(Named("default")));
+        registry.register("npm", Arc::new(Named("npm")));
+        registry.register("gh", Arc::new(Named("gh")));
+
+        let name = |path: &str| {
+            let (prefix, source, rest) = registry.route(path);
+            let (name, _, _) = source.parse_path(rest).unwrap();
+            (prefix.to_string(), name, rest.to_string())
+        };
+
+        assert_eq!(
+            name("npm/vue@3/index.js"),
+            ("npm/".into(), "npm".into(), "vue@3/index.js".into())
+        );
+        assert_eq!(
+            name("/gh/a/b@main"),
+            ("gh/".into(), "gh".into(), "a/b@main".into())
+        );
+        assert_eq!(
+            name("npmx@1.0.0"),
+            ("".into(), "default".into(), "npmx@1.0.0".into())
+        );
+    }
+
+    /// 返回处理请求的来源与解析结果，`gh/` 来源以 `PackageNotFound` 失败
+    async fn attempt(
+        prefix: &str,
+        (name, version, file): (String, Option<String>, Option<String>),
+    ) -> Result<String, AppError> {
+        if prefix == "gh/" {
+            return Err(AppError::PackageNotFound(format!("{}{}", prefix, name)));
+        }
+        Ok(format!("{}{} {:?} {:?}", prefix, name, version, file))
+    }
+
+    fn registry() -> SourceRegistry {
+        let npm: Arc<dyn PackageSource> = Arc::new(Npm);
+        let mut registry = SourceRegistry::new(npm.clone());
+        registry.register("npm", npm);
+        registry.register("gh", Arc::new(Repo));
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_fallback_to_default_source() {
+        let registry = registry();
+
+        // `gh` 来源无法解析：按默认来源中名为 `gh` 的包处理
+        let result = registry
+            .resolve_with_fallback("/gh/package.json", |prefix, _, parsed| {
+                attempt(prefix, parsed)
+            })
+            .await;
+        assert_eq!(result.unwrap(), r#"gh None Some("package.json")"#);
+
+        // 前缀来源能解析但找不到包时返回它自己的错误，不会访问默认来源
+        let mut calls = 0;
+        let result = registry
+            .resolve_with_fallback("gh/octo/missing", |prefix, _, parsed| {
+                calls += 1;
+                attempt(prefix, parsed)
+            })
+            .await;
+        assert!(matches!(result, Err(AppError::PackageNotFound(msg)) if msg == "gh/octo/missing"));
+        assert_eq!(calls, 1);
+
+        // 前缀来源正常处理
+        let result = registry
+            .resolve_with_fallback("npm/vue@3/index.js", |prefix, _, parsed| {
+                attempt(prefix, parsed)
+            })
+            .await;
+        assert_eq!(result.unwrap(), r#"npm/vue Some("3") Some("index.js")"#);
+
+        // `npm/` 与默认来源相同，无法解析时不会按名为 `npm` 的包重试
+        let result = registry
+            .resolve_with_fallback("npm/@x", |prefix, _, parsed| attempt(prefix, parsed))
+            .await;
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+    }
+
+    /// 按 `{user}/{repo}` 解析路径
+    struct Repo;
+
+    #[async_trait]
+    impl PackageSource for Repo {
+        fn parse_path(
+            &self,
+            path: &str,
+        ) -> Result<(String, Option<String>, Option<String>), AppError> {
+            crate::github::parse_path(path)
+                .map(|(user, repo, git_ref, file)| (format!("{}/{}", user, repo), git_ref, file))
+        }
+
+        async fn resolve_version(&self, name: &str, _: Option<&str>) -> Result<String, AppError> {
+            Err(AppError::PackageNotFound(name.to_string()))
+        }
+
+        async fn list_versions(&self, name: &str) -> Result<Vec<String>, AppError> {
+            Err(AppError::PackageNotFound(name.to_string()))
+        }
+
+        async fn fetch_package(&self, name: &str, _: &str) -> Result<Arc<PackageData>, AppError> {
+            Err(AppError::PackageNotFound(name.to_string()))
+        }
+
+        fn metadata_cache_key(&self, name: &str) -> String {
+            format!("metadata:gh/{}", name)
+        }
+
+        fn package_cache_key(&self, name: &str, version: &str) -> String {
+            format!("package:gh/{}@{}", name, version)
+        }
+    }
+
+    /// 按 npm 包的规则解析路径
+    struct Npm;
+
+    #[async_trait]
+    impl PackageSource for Npm {
+        fn parse_path(
+            &self,
+            path: &str,
+        ) -> Result<(String, Option<String>, Option<String>), AppError> {
+            crate::package::parse_path(path)
+        }
+
+        async fn resolve_version(&self, name: &str, _: Option<&str>) -> Result<String, AppError> {
+            Err(AppError::PackageNotFound(name.to_string()))
+        }
+
+        async fn list_versions(&self, name: &str) -> Result<Vec<String>, AppError> {
+            Err(AppError::PackageNotFound(name.to_string()))
+        }
+
+        async fn fetch_package(&self, name: &str, _: &str) -> Result<Arc<PackageData>, AppError> {
+            Err(AppError::PackageNotFound(name.to_string()))
+        }
+
+        fn metadata_cache_key(&self, name: &str) -> String {
+            name.to_string()
+        }
+
+        fn package_cache_key(&self, name: &str, version: &str) -> String {
+            format!("{}@{}", name, version)
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::fmt;
+use std::io::Write;
+
+/// 文件的 Subresource Integrity 哈希（sha256 / sha384 / sha512）
+///
+/// 在建立 tarball 索引时计算，随 `PackageData` 一起缓存。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Integrity {
+    sha256: [u8; 32],
+    sha384: [u8; 48],
+    sha512: [u8; 64],
+}
+
+impl Integrity {
+    #[cfg(test)]
+    pub fn of(content: &[u8]) -> Self {
+        let mut hasher = Hasher::default();
+        hasher.update(content);
+        hasher.finish()
+    }
+
+    /// `sha256-...`
+    pub fn sha256(&self) -> String {
+        format!("sha256-{}", BASE64_STANDARD.encode(self.sha256))
+    }
+
+    /// `sha384-...`，`<script integrity>` 常用的算法
+    pub fn sha384(&self) -> String {
+        format!("sha384-{}", BASE64_STANDARD.encode(self.sha384))
+    }
+
+    /// `sha512-...`
+    pub fn sha512(&self) -> String {
+        format!("sha512-{}", BASE64_STANDARD.encode(self.sha512))
+    }
+
+    /// sha256 的前 128 位（十六进制），用作文件的 ETag
+    pub fn short_sha256(&self) -> String {
+        hex::encode(&self.sha256[..16])
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "sha256": self.sha256(),
+            "sha384": self.sha384(),
+            "sha512": self.sha512(),
+        })
+    }
+}
+
+/// 三种哈希用空格分隔，可以直接作为 `integrity` 属性（浏览器使用最强的算法）
+impl fmt::Display for Integrity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.sha256(), self.sha384(), self.sha512())
+    }
+}
+
+/// 流式计算 [`Integrity`]
+#[derive(Default)]
+pub struct Hasher {
+    sha256: Sha256,
+    sha384: Sha384,
+    sha512: Sha512,
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        self.sha384.update(data);
+        self.sha512.update(data);
+    }
+
+    pub fn finish(self) -> Integrity {
+        Integrity {
+            sha256: self.sha256.finalize().into(),
+            sha384: self.sha384.finalize().into(),
+            sha512: self.sha512.finalize().into(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 引用文件的 HTML 标签，只支持 JavaScript 和 CSS
+pub fn tag(url: &str, file_path: &str, integrity: &Integrity) -> Option<String> {
+    let extension = file_path.rsplit('.').next().unwrap_or("");
+    let url = html_escape(url);
+    match extension {
+        "js" | "mjs" | "cjs" => Some(format!(
+            r#"<script src="{}" integrity="{}" crossorigin="anonymous"></script>"#,
+            url,
+            integrity.sha384()
+        )),
+        "css" => Some(format!(
+            r#"<link rel="stylesheet" href="{}" integrity="{}" crossorigin="anonymous">"#,
+            url,
+            integrity.sha384()
+        )),
+        _ => None,
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integrity() {
+        // echo -n "alert(1)" | openssl dgst -sha384 -binary | base64
+        let integrity = Integrity::of(b"alert(1)");
+        assert_eq!(
+            integrity.sha384(),
+            "sha384-HT2E9NfWiuQ/w1PRai+hTyqW16NIoCGA/m8VQDUopfAtcz6YQjtsMmQd5uRbVDpW"
+        );
+        assert!(integrity.sha256().starts_with("sha256-"));
+        assert_eq!(integrity.sha512().len(), "sha512-".len() + 88);
+
+        let mut hasher = Hasher::default();
+        hasher.write_all(b"alert").unwrap();
+        hasher.write_all(b"(1)").unwrap();
+        assert_eq!(hasher.finish(), integrity);
+        assert_eq!(integrity.to_string().split(' ').count(), 3);
+        // echo -n "alert(1)" | sha256sum
+        assert_eq!(integrity.short_sha256(), "6e11c72f7cf6bc383152dd16ddd5903a");
+    }
+
+    #[test]
+    fn test_tag() {
+        let integrity = Integrity::of(b"");
+        let script = tag("https://cdn.example.com/a@1.0.0/a.js", "a.js", &integrity).unwrap();
+        assert_eq!(
+            script,
+            format!(
+                r#"<script src="https://cdn.example.com/a@1.0.0/a.js" integrity="{}" crossorigin="anonymous"></script>"#,
+                integrity.sha384()
+            )
+        );
+        let link = tag("/a@1.0.0/a.css", "a.css", &integrity).unwrap();
+        assert!(link.starts_with(r#"<link rel="stylesheet" href="/a@1.0.0/a.css""#));
+        assert!(tag("/a@1.0.0/a.png", "a.png", &integrity).is_none());
+        assert!(tag("/a\"b.js", "a.js", &integrity)
+            .unwrap()
+            .contains("/a&quot;b.js"));
+    }
+}
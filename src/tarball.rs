@@ -0,0 +1,773 @@
+use crate::error::AppError;
+use crate::sri::{self, Integrity};
+use bytes::Bytes;
+use flate2::read::{DeflateDecoder, GzDecoder};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path};
+use std::sync::Arc;
+use tar::{Archive, EntryType};
+
+/// 解压后的大小上限（包括稀疏文件展开后的大小），超过时视为压缩炸弹拒绝整个归档。
+/// 需要小于包缓存的容量，否则单个包就能挤掉整个缓存
+const MAX_UNPACKED_SIZE: u64 = 256 * 1024 * 1024;
+
+/// 文件内容按该大小分块单独压缩，读取文件时只解压所在的块
+const CHUNK_SIZE: u64 = 256 * 1024;
+
+/// 建立索引时记录的文件信息
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileInfo {
+    /// 解压后的字节数
+    pub size: u64,
+    /// tar 头中的修改时间（Unix 时间戳）
+    pub mtime: u64,
+    /// SRI 哈希
+    pub integrity: Integrity,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct IndexedFile {
+    /// 在所有文件内容依次拼接而成的流中的偏移，长度为 `info.size`
+    offset: u64,
+    info: FileInfo,
+}
+
+/// 建立索引时跳过或改写的条目，归档仍然可用，但内容可能与作者的预期不同
+#[derive(Clone, Debug, PartialEq)]
+pub enum Anomaly {
+    /// 不在包根目录下的条目，被忽略
+    OutsideRoot(String),
+    /// 重复的条目，以最后一个为准
+    Duplicate(String),
+    /// 目标不存在的链接，被忽略
+    DanglingLink { path: String, target: String },
+}
+
+impl Anomaly {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Anomaly::OutsideRoot(path) => json!({"kind": "outside_root", "path": path}),
+            Anomaly::Duplicate(path) => json!({"kind": "duplicate", "path": path}),
+            Anomaly::DanglingLink { path, target } => {
+                json!({"kind": "dangling_link", "path": path, "target": target})
+            }
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::OutsideRoot(path) => write!(f, "entry outside package root: {}", path),
+            Anomaly::Duplicate(path) => write!(f, "duplicate entry, kept the last one: {}", path),
+            Anomaly::DanglingLink { path, target } => {
+                write!(f, "link with missing target: {} -> {}", path, target)
+            }
+        }
+    }
+}
+
+/// 分块压缩的文件内容以及每个文件的位置，按需解压单个文件
+///
+/// gzip 流无法从中间开始解压，因此建立索引时解压一次，把文件内容依次拼接后
+/// 每 [`CHUNK_SIZE`] 字节单独压缩为一块。缓存中占用的大小与原 tarball 相近，
+/// 读取文件时只需解压它所在的块。
+#[derive(Clone)]
+pub struct Index {
+    chunks: Arc<[Bytes]>,
+    files: HashMap<String, IndexedFile>,
+    anomalies: Vec<Anomaly>,
+}
+
+/// 包内的一个文件，可以移动到阻塞线程中解压
+pub struct ArchivedFile {
+    chunks: Arc<[Bytes]>,
+    offset: u64,
+    size: u64,
+}
+
+impl Index {
+    /// 为 gzip 压缩的 tar 包建立索引，规则与解压时相同：
+    ///
+    /// - 根目录：所有条目共享的第一级目录会被去掉（npm 包通常是 `package/`，
+    ///   但旧包或 GitHub 打包的归档可能使用其他名字）
+    /// - 含有 `..` 或绝对路径的条目、指向包外的链接视为恶意归档，整个归档被拒绝
+    /// - 重复条目以最后一个为准
+    /// - 符号链接和硬链接指向包内文件时与目标共享内容，目标不存在时忽略
+    /// - 目录、设备文件等其他条目类型被忽略
+    ///
+    /// 被忽略或覆盖的条目记录在 [`Index::anomalies`] 中。遍历时顺带计算每个文件的 SRI 哈希。
+    /// 解压后（包括稀疏文件展开后）超过 256 MiB 的归档被拒绝。
+    #[tracing::instrument(name = "tarball.index", skip_all, fields(bytes = archive.len()))]
+    pub fn build(archive: Bytes) -> Result<Self, AppError> {
+        Self::build_with_limit(&archive, MAX_UNPACKED_SIZE)
+    }
+
+    fn build_with_limit(archive: &[u8], limit: u64) -> Result<Self, AppError> {
+        // tar 流与展开后的文件内容分别计算大小，稀疏文件展开后可能远大于 tar 流
+        let reader = Limited {
+            inner: GzDecoder::new(archive),
+            remaining: limit,
+            limit,
+        };
+        let mut packer = Packer::new(limit);
+        let (files, anomalies) = scan(reader, |entry| {
+            let mtime = entry.header().mtime().unwrap_or(0);
+            let offset = packer.written;
+            let mut hasher = sri::Hasher::default();
+            // 头部声明的大小不可信，边读边写入，不据此预分配
+            let mut buffer = [0; 8192];
+            loop {
+                let n = entry.read(&mut buffer)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..n]);
+                packer.write_all(&buffer[..n])?;
+            }
+            Ok(IndexedFile {
+                offset,
+                info: FileInfo {
+                    size: packer.written - offset,
+                    mtime,
+                    integrity: hasher.finish(),
+                },
+            })
+        })?;
+        Ok(Self {
+            chunks: packer.finish().map_err(archive_error)?,
+            files,
+            anomalies,
+        })
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// 所有文件的路径（无序）
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// 文件的大小、修改时间与哈希，不需要解压
+    pub fn info(&self, path: &str) -> Option<&FileInfo> {
+        self.files.get(path).map(|file| &file.info)
+    }
+
+    /// 建立索引时发现的异常条目
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+
+    /// 占用的字节数（压缩后的文件内容）
+    pub fn size(&self) -> usize {
+        self.chunks.iter().map(Bytes::len).sum()
+    }
+
+    /// 取出单个文件，不存在时返回 None
+    pub fn file(&self, path: &str) -> Option<ArchivedFile> {
+        self.files.get(path).map(|file| ArchivedFile {
+            chunks: self.chunks.clone(),
+            offset: file.offset,
+            size: file.info.size,
+        })
+    }
+
+    /// 解压单个文件（阻塞），不存在时返回 None
+    pub fn read(&self, path: &str) -> Result<Option<Bytes>, AppError> {
+        self.file(path).map(ArchivedFile::read).transpose()
+    }
+}
+
+impl ArchivedFile {
+    /// 解压文件内容（阻塞），只解压文件跨越的块
+    #[tracing::instrument(name = "tarball.read", skip_all, fields(bytes = self.size))]
+    pub fn read(self) -> Result<Bytes, AppError> {
+        let corrupted = || AppError::InternalError("Corrupted package index".to_string());
+
+        let end = self.offset + self.size;
+        let mut content = Vec::with_capacity(self.size as usize);
+        let mut position = self.offset;
+        while position < end {
+            let chunk = self
+                .chunks
+                .get((position / CHUNK_SIZE) as usize)
+                .ok_or_else(corrupted)?;
+            let skip = position % CHUNK_SIZE;
+            let len = (end - position).min(CHUNK_SIZE - skip);
+
+            let mut decoder = DeflateDecoder::new(&chunk[..]);
+            io::copy(&mut (&mut decoder).take(skip), &mut io::sink()).map_err(|_| corrupted())?;
+            let read = decoder
+                .take(len)
+                .read_to_end(&mut content)
+                .map_err(|_| corrupted())?;
+            if read as u64 != len {
+                return Err(corrupted());
+            }
+            position += len;
+        }
+        Ok(Bytes::from(content))
+    }
+}
+
+/// 将文件内容依次写入，每 [`CHUNK_SIZE`] 字节单独压缩为一块，总量超过 `limit` 时报错
+struct Packer {
+    chunks: Vec<Bytes>,
+    buffer: Vec<u8>,
+    written: u64,
+    limit: u64,
+}
+
+impl Packer {
+    fn new(limit: u64) -> Self {
+        Self {
+            chunks: Vec::new(),
+            buffer: Vec::with_capacity(CHUNK_SIZE as usize),
+            written: 0,
+            limit,
+        }
+    }
+
+    /// 压缩缓冲区中的内容，作为新的一块
+    fn seal(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buffer)?;
+        self.chunks.push(Bytes::from(encoder.finish()?));
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<Arc<[Bytes]>> {
+        self.seal()?;
+        Ok(self.chunks.into())
+    }
+}
+
+impl Write for Packer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.written + data.len() as u64 > self.limit {
+            return Err(too_large(self.limit));
+        }
+        let n = data.len().min(CHUNK_SIZE as usize - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..n]);
+        self.written += n as u64;
+        if self.buffer.len() == CHUNK_SIZE as usize {
+            self.seal()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 读取超过 `limit` 字节时报错，用于限制解压后 tar 流的大小
+struct Limited<R> {
+    inner: R,
+    remaining: u64,
+    limit: u64,
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.remaining = self
+            .remaining
+            .checked_sub(n as u64)
+            .ok_or_else(|| too_large(self.limit))?;
+        Ok(n)
+    }
+}
+
+fn too_large(limit: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("archive exceeds {} bytes when unpacked", limit),
+    )
+}
+
+/// `git archive` 生成的归档在 pax 全局头的 `comment` 中记录提交 sha，没有时返回 None
+pub fn commit_id(archive: &[u8]) -> Option<String> {
+    let mut archive = Archive::new(GzDecoder::new(archive));
+    let mut entry = archive.entries().ok()?.next()?.ok()?;
+    if entry.header().entry_type() != EntryType::XGlobalHeader {
+        return None;
+    }
+    let extensions = entry.pax_extensions().ok()??;
+    for extension in extensions {
+        let extension = extension.ok()?;
+        if extension.key() == Ok("comment") {
+            return extension.value().ok().map(str::to_string);
+        }
+    }
+    None
+}
+
+/// 链接条目，遍历完成后再解析目标
+struct PendingLink {
+    path: String,
+    target: String,
+}
+
+/// 遍历归档中的条目，`read` 为每个普通文件生成内容或位置，返回 `相对路径 -> 结果` 与异常条目
+fn scan<R: Read, T: Clone>(
+    reader: R,
+    mut read: impl FnMut(&mut tar::Entry<R>) -> io::Result<T>,
+) -> Result<(HashMap<String, T>, Vec<Anomaly>), AppError> {
+    let mut archive = Archive::new(reader);
+
+    let mut raw_files: Vec<(Vec<String>, T)> = Vec::new();
+    let mut raw_links: Vec<(Vec<String>, Vec<String>, bool)> = Vec::new();
+
+    for entry in archive.entries().map_err(archive_error)? {
+        let mut entry = entry.map_err(archive_error)?;
+        let entry_type = entry.header().entry_type();
+
+        let path = entry.path().map_err(archive_error)?.into_owned();
+        let components = normalize(&path)?;
+        if components.is_empty() {
+            continue;
+        }
+
+        match entry_type {
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                raw_files.push((components, read(&mut entry).map_err(archive_error)?));
+            }
+            EntryType::Symlink | EntryType::Link => {
+                let Some(target) = entry.link_name().map_err(archive_error)? else {
+                    continue;
+                };
+                let is_hard = entry_type == EntryType::Link;
+                // 硬链接目标是归档内路径，符号链接目标相对于链接所在目录
+                let target: Vec<String> = target
+                    .to_string_lossy()
+                    .split('/')
+                    .map(str::to_string)
+                    .collect();
+                raw_links.push((components, target, is_hard));
+            }
+            _ => {}
+        }
+    }
+
+    let root = detect_root(raw_files.iter().map(|(c, _)| c));
+    let strip = |components: &[String]| -> Option<String> {
+        match &root {
+            Some(root) if components.first() == Some(root) && components.len() > 1 => {
+                Some(components[1..].join("/"))
+            }
+            Some(_) => None,
+            None => Some(components.join("/")),
+        }
+    };
+
+    let mut anomalies = Vec::new();
+    let mut files = HashMap::with_capacity(raw_files.len());
+    for (components, contents) in raw_files {
+        let Some(path) = strip(&components) else {
+            anomalies.push(Anomaly::OutsideRoot(components.join("/")));
+            continue;
+        };
+        if files.insert(path.clone(), contents).is_some() {
+            anomalies.push(Anomaly::Duplicate(path));
+        }
+    }
+
+    let mut pending = Vec::new();
+    for (components, raw_target, is_hard) in raw_links {
+        let Some(path) = strip(&components) else {
+            anomalies.push(Anomaly::OutsideRoot(components.join("/")));
+            continue;
+        };
+        let base = if is_hard {
+            &[][..]
+        } else {
+            &components[..components.len() - 1]
+        };
+        let Some(target) = normalize_segments(base, &raw_target).and_then(|t| strip(&t)) else {
+            return Err(AppError::InvalidArchive(format!(
+                "Link points outside the package: {} -> {}",
+                path,
+                raw_target.join("/")
+            )));
+        };
+        pending.push(PendingLink { path, target });
+    }
+    for link in resolve_links(&mut files, pending) {
+        anomalies.push(Anomaly::DanglingLink {
+            path: link.path,
+            target: link.target,
+        });
+    }
+
+    if files.is_empty() {
+        return Err(AppError::InvalidArchive(
+            "Archive contains no files".to_string(),
+        ));
+    }
+    for anomaly in &anomalies {
+        tracing::warn!("Archive anomaly: {}", anomaly);
+    }
+
+    Ok((files, anomalies))
+}
+
+/// 将条目路径拆分为各级名称，拒绝 `..`、绝对路径等不安全的路径
+fn normalize(path: &Path) -> Result<Vec<String>, AppError> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(AppError::InvalidArchive(format!(
+                    "Unsafe path in archive: {}",
+                    path.display()
+                )));
+            }
+        }
+    }
+    Ok(components)
+}
+
+/// 在 `base` 目录下解析链接目标，越出归档根目录时返回 None
+fn normalize_segments(base: &[String], target: &[String]) -> Option<Vec<String>> {
+    if target.first().map(|s| s.is_empty()).unwrap_or(true) && target.len() > 1 {
+        // 绝对路径
+        return None;
+    }
+
+    let mut resolved = base.to_vec();
+    for segment in target {
+        match segment.as_str() {
+            "" | "." => {}
+            ".." => {
+                resolved.pop()?;
+            }
+            s => resolved.push(s.to_string()),
+        }
+    }
+    Some(resolved)
+}
+
+/// 找出所有文件共享的第一级目录
+fn detect_root<'a>(mut paths: impl Iterator<Item = &'a Vec<String>>) -> Option<String> {
+    let first = paths.next()?;
+    if first.len() < 2 {
+        return None;
+    }
+    let root = &first[0];
+
+    let mut shared = true;
+    let mut under_package = first[0] == "package";
+    for path in paths {
+        if path.len() < 2 || &path[0] != root {
+            shared = false;
+        }
+        if path.len() >= 2 && path[0] == "package" {
+            under_package = true;
+        }
+    }
+
+    if shared {
+        Some(root.clone())
+    } else if under_package {
+        // 根目录不一致时按 npm 的约定使用 package/
+        Some("package".to_string())
+    } else {
+        None
+    }
+}
+
+/// 将链接替换为目标文件的内容（或位置），支持链接指向链接，返回目标不存在的链接
+fn resolve_links<T: Clone>(
+    files: &mut HashMap<String, T>,
+    mut pending: Vec<PendingLink>,
+) -> Vec<PendingLink> {
+    loop {
+        let before = pending.len();
+        pending.retain(|link| match files.get(&link.target) {
+            Some(contents) => {
+                files.insert(link.path.clone(), contents.clone());
+                false
+            }
+            None => true,
+        });
+        if pending.is_empty() || pending.len() == before {
+            break;
+        }
+    }
+    pending
+}
+
+fn archive_error(err: io::Error) -> AppError {
+    AppError::InvalidArchive(format!("Failed to read archive: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use tar::{Builder, Header};
+
+    /// 建立索引后读出所有文件
+    fn extract(bytes: &[u8]) -> Result<HashMap<String, Bytes>, AppError> {
+        let index = Index::build(Bytes::copy_from_slice(bytes))?;
+        Ok(index
+            .paths()
+            .map(|path| (path.to_string(), index.read(path).unwrap().unwrap()))
+            .collect())
+    }
+
+    enum Entry<'a> {
+        File(&'a str, &'a str),
+        Symlink(&'a str, &'a str),
+        Hardlink(&'a str, &'a str),
+    }
+
+    /// 直接写入路径字节，绕过 tar::Builder 对 `..` 的检查
+    fn header(path: &str, entry_type: EntryType, size: u64) -> Header {
+        let mut header = Header::new_gnu();
+        let name = &mut header.as_gnu_mut().unwrap().name;
+        name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_cksum();
+        header
+    }
+
+    fn tarball(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for entry in entries {
+            match entry {
+                Entry::File(path, contents) => {
+                    let header = header(path, EntryType::Regular, contents.len() as u64);
+                    builder.append(&header, contents.as_bytes()).unwrap();
+                }
+                Entry::Symlink(path, target) | Entry::Hardlink(path, target) => {
+                    let entry_type = if matches!(entry, Entry::Symlink(..)) {
+                        EntryType::Symlink
+                    } else {
+                        EntryType::Link
+                    };
+                    let mut header = header(path, entry_type, 0);
+                    header.set_link_name(target).unwrap();
+                    header.set_cksum();
+                    builder.append(&header, std::io::empty()).unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_strips_package_root() {
+        let files = extract(&tarball(&[
+            Entry::File("package/package.json", "{}"),
+            Entry::File("package/dist/index.js", "1"),
+        ]))
+        .unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files["dist/index.js"], &b"1"[..]);
+    }
+
+    #[test]
+    fn test_detects_custom_root() {
+        let files = extract(&tarball(&[
+            Entry::File("node-v1.2.3/package.json", "{}"),
+            Entry::File("node-v1.2.3/lib/a.js", "a"),
+        ]))
+        .unwrap();
+        assert!(files.contains_key("package.json"));
+        assert!(files.contains_key("lib/a.js"));
+    }
+
+    #[test]
+    fn test_rejects_parent_segments() {
+        let result = extract(&tarball(&[
+            Entry::File("package/package.json", "{}"),
+            Entry::File("package/../../etc/passwd", "x"),
+        ]));
+        assert!(matches!(result, Err(AppError::InvalidArchive(_))));
+    }
+
+    #[test]
+    fn test_duplicate_entries_keep_last() {
+        let index = Index::build(Bytes::from(tarball(&[
+            Entry::File("package/index.js", "old"),
+            Entry::File("package/./index.js", "new"),
+        ])))
+        .unwrap();
+        assert_eq!(index.read("index.js").unwrap().unwrap(), &b"new"[..]);
+        assert_eq!(
+            index.anomalies(),
+            [Anomaly::Duplicate("index.js".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_entries_outside_root() {
+        let index = Index::build(Bytes::from(tarball(&[
+            Entry::File("package/index.js", "a"),
+            Entry::File("other/b.js", "b"),
+        ])))
+        .unwrap();
+        assert!(index.contains("index.js"));
+        assert!(!index.contains("b.js"));
+        assert_eq!(
+            index.anomalies(),
+            [Anomaly::OutsideRoot("other/b.js".to_string())]
+        );
+        assert_eq!(
+            index.anomalies()[0].to_json(),
+            json!({"kind": "outside_root", "path": "other/b.js"})
+        );
+    }
+
+    #[test]
+    fn test_links() {
+        let index = Index::build(Bytes::from(tarball(&[
+            Entry::File("package/dist/a.js", "a"),
+            Entry::Symlink("package/index.js", "dist/a.js"),
+            Entry::Hardlink("package/b.js", "package/dist/a.js"),
+            Entry::Symlink("package/missing.js", "nope.js"),
+        ])))
+        .unwrap();
+        assert_eq!(index.read("index.js").unwrap().unwrap(), &b"a"[..]);
+        assert_eq!(index.read("b.js").unwrap().unwrap(), &b"a"[..]);
+        assert!(!index.contains("missing.js"));
+        assert_eq!(
+            index.anomalies(),
+            [Anomaly::DanglingLink {
+                path: "missing.js".to_string(),
+                target: "nope.js".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rejects_links_outside_package() {
+        for link in [
+            Entry::Symlink("package/passwd", "../../etc/passwd"),
+            Entry::Symlink("package/passwd", "/etc/passwd"),
+            Entry::Hardlink("package/passwd", "other/passwd"),
+        ] {
+            let result = extract(&tarball(&[Entry::File("package/index.js", "a"), link]));
+            assert!(matches!(result, Err(AppError::InvalidArchive(_))));
+        }
+    }
+
+    #[test]
+    fn test_reads_single_file() {
+        let index = Index::build(Bytes::from(tarball(&[
+            Entry::File("package/a.js", &"a".repeat(1000)),
+            Entry::File("package/b.js", "b"),
+            Entry::Symlink("package/c.js", "b.js"),
+        ])))
+        .unwrap();
+
+        assert_eq!(index.read("b.js").unwrap().unwrap(), &b"b"[..]);
+        assert_eq!(index.read("c.js").unwrap().unwrap(), &b"b"[..]);
+        assert_eq!(index.read("a.js").unwrap().unwrap().len(), 1000);
+        assert!(index.read("d.js").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_reads_across_chunks() {
+        // 周期与块大小互质，偏移错位时内容会不同
+        let pattern =
+            |len: usize| -> String { (0..len).map(|i| (b'a' + (i % 23) as u8) as char).collect() };
+        let small = pattern(100);
+        let large = pattern(3 * CHUNK_SIZE as usize + 17);
+        let index = Index::build(Bytes::from(tarball(&[
+            Entry::File("package/small.js", &small),
+            Entry::File("package/large.js", &large),
+            Entry::File("package/after.js", "after"),
+        ])))
+        .unwrap();
+
+        assert_eq!(index.read("small.js").unwrap().unwrap(), small.as_bytes());
+        assert_eq!(index.read("large.js").unwrap().unwrap(), large.as_bytes());
+        assert_eq!(index.read("after.js").unwrap().unwrap(), &b"after"[..]);
+        // 只保存压缩后的内容
+        assert_eq!(index.chunks.len(), 4);
+        assert!(index.size() < large.len() / 10);
+    }
+
+    /// 只有一个数据块的 GNU 稀疏文件，数据之后补零到 `real_size`
+    fn sparse_tarball(path: &str, data: &str, real_size: u64) -> Vec<u8> {
+        let mut header = header(path, EntryType::GNUSparse, data.len() as u64);
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.sparse[0].set_offset(0);
+        gnu.sparse[0].set_length(data.len() as u64);
+        gnu.sparse[1].set_offset(real_size);
+        gnu.sparse[1].set_length(0);
+        gnu.set_real_size(real_size);
+        header.set_cksum();
+
+        let mut builder = Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        builder.append(&header, data.as_bytes()).unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_sparse_files() {
+        let index = Index::build(Bytes::from(sparse_tarball("package/a.bin", "abc", 10))).unwrap();
+        assert_eq!(
+            index.read("a.bin").unwrap().unwrap(),
+            &b"abc\0\0\0\0\0\0\0"[..]
+        );
+        assert_eq!(index.info("a.bin").unwrap().size, 10);
+
+        // 声明展开后 1 TiB：不按声明的大小预分配，超过上限时拒绝
+        let archive = sparse_tarball("package/a.bin", "abc", 1 << 40);
+        let result = Index::build_with_limit(&archive, 64 * 1024);
+        assert!(matches!(result, Err(AppError::InvalidArchive(_))));
+    }
+
+    #[test]
+    fn test_unpacked_size_limit() {
+        let archive = tarball(&[Entry::File("package/a.js", &"a".repeat(100_000))]);
+        assert!(archive.len() < 10_000);
+        let result = Index::build_with_limit(&archive, 64 * 1024);
+        assert!(matches!(result, Err(AppError::InvalidArchive(_))));
+        assert!(Index::build_with_limit(&archive, 1024 * 1024).is_ok());
+    }
+
+    #[test]
+    fn test_file_info() {
+        let index = Index::build(Bytes::from(tarball(&[Entry::File(
+            "package/index.js",
+            "alert(1)",
+        )])))
+        .unwrap();
+        let info = index.info("index.js").unwrap();
+        assert_eq!(info.size, 8);
+        assert_eq!(info.integrity, Integrity::of(b"alert(1)"));
+        assert!(index.info("missing.js").is_none());
+    }
+
+    #[test]
+    fn test_invalid_archives() {
+        assert!(matches!(
+            extract(b"not a tarball"),
+            Err(AppError::InvalidArchive(_))
+        ));
+        assert!(matches!(
+            extract(&tarball(&[])),
+            Err(AppError::InvalidArchive(_))
+        ));
+    }
+}
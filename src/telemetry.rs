@@ -0,0 +1,246 @@
+use axum::http::HeaderMap;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use serde_json::{json, Map, Value};
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::time::UNIX_EPOCH;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// 未设置 `OTEL_SERVICE_NAME` 时上报的服务名
+const DEFAULT_SERVICE_NAME: &str = "byr-jsdelivr";
+
+/// 链路追踪导出
+///
+/// 包含 tracing span 到 OpenTelemetry 的转换层，退出前需要调用
+/// [`Telemetry::shutdown`] 把缓冲的 span 发送出去。
+pub struct Telemetry {
+    provider: TracerProvider,
+}
+
+impl Telemetry {
+    /// 从环境变量初始化，未配置导出方式时返回 None
+    ///
+    /// - `OTEL_TRACES_EXPORTER`: `otlp` / `stdout` / `none`，
+    ///   未设置时配置了 OTLP 地址就使用 `otlp`
+    /// - `OTEL_EXPORTER_OTLP_ENDPOINT` / `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`:
+    ///   collector 地址（OTLP/HTTP protobuf，如 `http://collector:4318`）
+    /// - `OTEL_SERVICE_NAME`: 服务名，默认 `byr-jsdelivr`
+    pub fn from_env() -> Option<Self> {
+        let exporter = std::env::var("OTEL_TRACES_EXPORTER").unwrap_or_else(|_| {
+            let configured = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok()
+                || std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").is_ok();
+            if configured { "otlp" } else { "none" }.to_string()
+        });
+
+        let builder = TracerProvider::builder().with_resource(resource());
+        let provider = match exporter.as_str() {
+            "otlp" => {
+                let exporter = opentelemetry_otlp::SpanExporter::builder()
+                    .with_http()
+                    .build()
+                    .expect("Failed to create OTLP exporter");
+                builder.with_batch_exporter(exporter, runtime::Tokio)
+            }
+            "stdout" => builder.with_simple_exporter(StdoutExporter),
+            "none" => return None,
+            other => panic!("Unknown OTEL_TRACES_EXPORTER: {}", other),
+        }
+        .build();
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        Some(Self { provider })
+    }
+
+    /// tracing 订阅层，把 span 交给 OpenTelemetry
+    pub fn layer<S>(&self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.provider.tracer(DEFAULT_SERVICE_NAME))
+    }
+
+    /// 发送缓冲中的 span 并关闭导出
+    pub fn shutdown(self) {
+        if let Err(e) = self.provider.shutdown() {
+            tracing::warn!("Failed to shut down trace exporter: {}", e);
+        }
+    }
+}
+
+fn resource() -> Resource {
+    let service_name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+    Resource::default().merge(&Resource::new([
+        KeyValue::new("service.name", service_name),
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+    ]))
+}
+
+/// 让 span 接续请求头 `traceparent` 中的链路
+pub fn set_parent_from(span: &tracing::Span, headers: &HeaderMap) {
+    let context =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    span.set_parent(context);
+}
+
+/// 当前 span 的链路传播头（`traceparent` 等），附加到上游请求上
+pub fn propagation_headers() -> Vec<(String, String)> {
+    let context = tracing::Span::current().context();
+    let mut headers = HeaderInjector(Vec::new());
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+    headers.0
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+struct HeaderInjector(Vec<(String, String)>);
+
+impl Injector for HeaderInjector {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.push((key.to_string(), value));
+    }
+}
+
+/// 每个 span 输出一行 JSON 到标准输出，便于在没有 collector 时调试
+#[derive(Debug)]
+struct StdoutExporter;
+
+impl SpanExporter for StdoutExporter {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        let mut stdout = std::io::stdout().lock();
+        for span in batch {
+            let _ = writeln!(stdout, "{}", span_json(&span));
+        }
+        Box::pin(std::future::ready(Ok(())))
+    }
+}
+
+fn span_json(span: &SpanData) -> Value {
+    let attributes: Map<String, Value> = span
+        .attributes
+        .iter()
+        .map(|kv| (kv.key.to_string(), json!(kv.value.as_str())))
+        .collect();
+    let start = span
+        .start_time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let duration = span
+        .end_time
+        .duration_since(span.start_time)
+        .unwrap_or_default();
+
+    json!({
+        "trace_id": span.span_context.trace_id().to_string(),
+        "span_id": span.span_context.span_id().to_string(),
+        "parent_span_id": span.parent_span_id.to_string(),
+        "name": span.name,
+        "start_time": start.as_millis() as u64,
+        "duration_ms": duration.as_secs_f64() * 1000.0,
+        "attributes": attributes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_pipeline_spans() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let telemetry = Telemetry { provider };
+        let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request");
+            let _guard = span.enter();
+            let (name, range, _) = crate::package::parse_path("vue@^3.3.0/index.js").unwrap();
+            let metadata = json!({
+                "name": name,
+                "dist-tags": {"latest": "3.3.4"},
+                "versions": {"3.3.4": {}}
+            });
+            crate::semver_utils::resolve_version(&metadata, range.as_deref()).unwrap();
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let find = |name: &str| spans.iter().find(|span| span.name == name).unwrap();
+        let request = find("request");
+        for name in ["parse_path", "resolve_version"] {
+            let span = find(name);
+            assert_eq!(span.parent_span_id, request.span_context.span_id());
+            assert_eq!(
+                span.span_context.trace_id(),
+                request.span_context.trace_id()
+            );
+        }
+        let path = find("parse_path");
+        assert!(path
+            .attributes
+            .iter()
+            .any(|kv| kv.key.as_str() == "path" && kv.value.as_str() == "vue@^3.3.0/index.js"));
+    }
+
+    #[test]
+    fn test_trace_context_propagation() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let telemetry = Telemetry { provider };
+        let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", traceparent.parse().unwrap());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request");
+            set_parent_from(&span, &headers);
+            let _guard = span.enter();
+
+            let context = span.context();
+            assert_eq!(
+                context.span().span_context().trace_id().to_string(),
+                "0af7651916cd43dd8448eb211c80319c"
+            );
+
+            // 转发给上游的 traceparent 保持同一条链路
+            let outgoing = propagation_headers();
+            let (key, value) = outgoing
+                .iter()
+                .find(|(key, _)| key == "traceparent")
+                .unwrap();
+            assert_eq!(key, "traceparent");
+            assert!(value.starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+            assert!(!value.contains("b7ad6b7169203331"));
+        });
+    }
+}
@@ -0,0 +1,33 @@
+//! 测试共用的 tarball 构造函数
+
+use flate2::{write::GzEncoder, Compression};
+use std::path::Path;
+
+/// 生成 gzip 压缩的 tar 包，`files` 为 (归档内路径, 内容)
+pub fn tarball(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, path, content.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// 生成 npm 包的 tarball：`package.json` 与内容为版本号的 `index.js`
+pub fn npm_tarball(name: &str, version: &str) -> Vec<u8> {
+    let package_json = format!(r#"{{"name":"{}","version":"{}"}}"#, name, version);
+    tarball(&[
+        ("package/package.json", &package_json),
+        ("package/index.js", version),
+    ])
+}
+
+/// 将 npm 包的 tarball 写入 `path`，按需创建上级目录
+pub fn write_tarball(path: &Path, name: &str, version: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, npm_tarball(name, version)).unwrap();
+}
@@ -0,0 +1,579 @@
+use crate::access;
+use crate::error::AppError;
+use crate::telemetry;
+use bytes::Bytes;
+use reqwest::{Client, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 按 Content-Length 预分配响应体缓冲区的上限
+const MAX_PREALLOCATE: u64 = 16 * 1024 * 1024;
+
+/// 访问上游（registry、codeload 等）的超时、重试与熔断配置
+#[derive(Debug, Clone)]
+pub struct UpstreamConfig {
+    /// 建立连接的超时
+    pub connect_timeout: Duration,
+    /// 等待响应头以及两次读取响应体之间的超时
+    pub read_timeout: Duration,
+    /// 单次请求（含响应体）的总超时
+    pub total_timeout: Duration,
+    /// 响应体大小上限（字节）
+    pub max_size: u64,
+    /// 失败后的最大重试次数
+    pub retries: u32,
+    /// 重试退避的基础时长，第 n 次重试在 `[0, base * 2^n)` 中随机等待
+    pub backoff_base: Duration,
+    /// 连续失败多少次后熔断
+    pub breaker_threshold: u32,
+    /// 熔断持续时间，之后放行一个试探请求
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(30),
+            total_timeout: Duration::from_secs(120),
+            max_size: 256 * 1024 * 1024,
+            retries: 2,
+            backoff_base: Duration::from_millis(200),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl UpstreamConfig {
+    /// 从环境变量读取配置，时长单位均为秒
+    ///
+    /// - `UPSTREAM_CONNECT_TIMEOUT` / `UPSTREAM_READ_TIMEOUT` / `UPSTREAM_TIMEOUT`
+    /// - `UPSTREAM_MAX_SIZE`: 响应体大小上限（字节）
+    /// - `UPSTREAM_RETRIES`: 最大重试次数
+    /// - `UPSTREAM_BREAKER_THRESHOLD` / `UPSTREAM_BREAKER_COOLDOWN`
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let seconds = |name: &str, default: Duration| {
+            std::env::var(name)
+                .map(|v| {
+                    Duration::from_secs_f64(
+                        v.parse()
+                            .unwrap_or_else(|_| panic!("{} must be a number of seconds", name)),
+                    )
+                })
+                .unwrap_or(default)
+        };
+        let number = |name: &str, default: u32| {
+            std::env::var(name)
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|_| panic!("{} must be a non-negative integer", name))
+                })
+                .unwrap_or(default)
+        };
+
+        Self {
+            connect_timeout: seconds("UPSTREAM_CONNECT_TIMEOUT", defaults.connect_timeout),
+            read_timeout: seconds("UPSTREAM_READ_TIMEOUT", defaults.read_timeout),
+            total_timeout: seconds("UPSTREAM_TIMEOUT", defaults.total_timeout),
+            max_size: std::env::var("UPSTREAM_MAX_SIZE")
+                .map(|v| {
+                    v.parse()
+                        .expect("UPSTREAM_MAX_SIZE must be a number of bytes")
+                })
+                .unwrap_or(defaults.max_size),
+            retries: number("UPSTREAM_RETRIES", defaults.retries),
+            backoff_base: defaults.backoff_base,
+            breaker_threshold: number("UPSTREAM_BREAKER_THRESHOLD", defaults.breaker_threshold)
+                .max(1),
+            breaker_cooldown: seconds("UPSTREAM_BREAKER_COOLDOWN", defaults.breaker_cooldown),
+        }
+    }
+}
+
+/// 带超时、重试与按主机熔断的 HTTP GET 客户端
+pub struct UpstreamClient {
+    client: Client,
+    config: UpstreamConfig,
+    breakers: Mutex<HashMap<String, Arc<CircuitBreaker>>>,
+}
+
+/// 单次请求的失败原因
+enum Failure {
+    /// 可以重试的失败（连接错误、超时、5xx）
+    Retryable(AppError),
+    /// 不应重试，也不计入熔断（例如 404）
+    Fatal(AppError),
+    /// 上游限流（429）：不重试，也不影响熔断状态（主机本身是健康的，但也没有成功应答）
+    Throttled(AppError),
+}
+
+impl UpstreamClient {
+    /// `client` 应已设置连接超时（见 [`UpstreamConfig::connect_timeout`]）
+    pub fn new(client: Client, config: UpstreamConfig) -> Self {
+        Self {
+            client,
+            config,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// GET 并读取完整响应体
+    ///
+    /// 404/410 返回 `NotFound`，401/403 返回 `UpstreamUnauthorized`，429 立即返回带有上游
+    /// `Retry-After` 的 `UpstreamRateLimited`，其他客户端错误返回 `UpstreamError`，
+    /// 超过大小上限返回 `TooLarge`；连接错误与 5xx 重试后返回 `UpstreamError`，
+    /// 超时返回 `UpstreamTimeout`，主机处于熔断状态时返回 `UpstreamUnavailable`。
+    #[tracing::instrument(name = "upstream.get", skip(self))]
+    pub async fn get(&self, url: &str) -> Result<Bytes, AppError> {
+        let started = Instant::now();
+        let result = self.get_with_retries(url).await;
+        access::record_upstream(
+            started.elapsed(),
+            result.as_ref().map_or(0, |body| body.len()),
+        );
+        result
+    }
+
+    async fn get_with_retries(&self, url: &str) -> Result<Bytes, AppError> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .ok_or_else(|| AppError::InvalidRequest(format!("Invalid upstream URL: {}", url)))?;
+        let breaker = self.breaker(&host);
+
+        let mut attempt = 0;
+        loop {
+            if let Err(retry_after) = breaker.allow() {
+                return Err(AppError::UpstreamUnavailable(format!(
+                    "{} is unavailable, retry after {}s",
+                    host,
+                    retry_after.as_secs().max(1)
+                )));
+            }
+
+            match self.attempt(url).await {
+                Ok(body) => {
+                    breaker.record_success();
+                    return Ok(body);
+                }
+                Err(Failure::Fatal(e)) => {
+                    // 上游能正常应答，说明主机是健康的
+                    breaker.record_success();
+                    return Err(e);
+                }
+                Err(Failure::Throttled(e)) => return Err(e),
+                Err(Failure::Retryable(e)) => {
+                    breaker.record_failure();
+                    if attempt >= self.config.retries {
+                        return Err(e);
+                    }
+
+                    let delay = backoff(self.config.backoff_base, attempt);
+                    tracing::warn!(
+                        "Upstream request to {} failed ({}), retrying in {:?}",
+                        url,
+                        e,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// GET 并解析 JSON
+    pub async fn get_json(&self, url: &str) -> Result<serde_json::Value, AppError> {
+        let body = self.get(url).await?;
+        serde_json::from_slice(&body)
+            .map_err(|e| AppError::UpstreamError(format!("Invalid JSON from {}: {}", url, e)))
+    }
+
+    async fn attempt(&self, url: &str) -> Result<Bytes, Failure> {
+        let mut request = self.client.get(url).timeout(self.config.total_timeout);
+        if let Some(id) = access::request_id() {
+            request = request.header(access::X_REQUEST_ID.as_str(), id);
+        }
+        for (name, value) in telemetry::propagation_headers() {
+            request = request.header(name, value);
+        }
+        let request = request.send();
+        let mut response = match tokio::time::timeout(self.config.read_timeout, request).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => return Err(Failure::Retryable(AppError::from(e))),
+            Err(_) => return Err(Failure::Retryable(timeout_error(url))),
+        };
+
+        let status = response.status();
+        let message = format!("{} returned {}", url, status);
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(parse_retry_after);
+                return Err(Failure::Throttled(AppError::UpstreamRateLimited(
+                    message,
+                    retry_after,
+                )));
+            }
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                return Err(Failure::Fatal(AppError::UpstreamUnauthorized(message)))
+            }
+            StatusCode::REQUEST_TIMEOUT => {
+                return Err(Failure::Retryable(AppError::UpstreamTimeout(message)))
+            }
+            StatusCode::NOT_FOUND | StatusCode::GONE => {
+                return Err(Failure::Fatal(AppError::NotFound(message)))
+            }
+            status if status.is_server_error() => {
+                return Err(Failure::Retryable(AppError::UpstreamError(message)))
+            }
+            status if !status.is_success() => {
+                return Err(Failure::Fatal(AppError::UpstreamError(message)))
+            }
+            _ => {}
+        }
+
+        let too_large = || {
+            Failure::Fatal(AppError::TooLarge(format!(
+                "{} is larger than {} bytes",
+                url, self.config.max_size
+            )))
+        };
+        if response.content_length().unwrap_or(0) > self.config.max_size {
+            return Err(too_large());
+        }
+
+        // Content-Length 只作为预分配的参考，不完全信任
+        let capacity = response.content_length().unwrap_or(0).min(MAX_PREALLOCATE);
+        let mut body = Vec::with_capacity(capacity as usize);
+        loop {
+            match tokio::time::timeout(self.config.read_timeout, response.chunk()).await {
+                Ok(Ok(Some(chunk))) => {
+                    if (body.len() + chunk.len()) as u64 > self.config.max_size {
+                        return Err(too_large());
+                    }
+                    body.extend_from_slice(&chunk)
+                }
+                Ok(Ok(None)) => return Ok(Bytes::from(body)),
+                Ok(Err(e)) => return Err(Failure::Retryable(AppError::from(e))),
+                Err(_) => return Err(Failure::Retryable(timeout_error(url))),
+            }
+        }
+    }
+
+    fn breaker(&self, host: &str) -> Arc<CircuitBreaker> {
+        self.breakers
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| {
+                Arc::new(CircuitBreaker::new(
+                    self.config.breaker_threshold,
+                    self.config.breaker_cooldown,
+                ))
+            })
+            .clone()
+    }
+}
+
+fn timeout_error(url: &str) -> AppError {
+    AppError::UpstreamTimeout(format!("Timed out waiting for {}", url))
+}
+
+/// 解析 `Retry-After`：秒数或 HTTP 日期，已经过去的日期视为 0 秒
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+/// 全抖动指数退避：在 `[0, base * 2^attempt)` 中随机取值
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    let max = base.saturating_mul(1 << attempt.min(10));
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_nanos(random % (max.as_nanos() as u64).max(1))
+}
+
+#[derive(Debug)]
+enum BreakerState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// 熔断结束后放行一个试探请求
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+/// 单个上游主机的熔断器
+#[derive(Debug)]
+struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+            threshold,
+            cooldown,
+        }
+    }
+
+    /// 是否允许发出请求，不允许时返回剩余的熔断时间
+    fn allow(&self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            BreakerState::Closed { .. } => Ok(()),
+            BreakerState::Open { until } if now < until => Err(until - now),
+            BreakerState::Open { .. } => {
+                *state = BreakerState::HalfOpen { since: now };
+                Ok(())
+            }
+            // 试探请求迟迟没有结果（例如被取消）时再放行一个
+            BreakerState::HalfOpen { since } if now.duration_since(since) >= self.cooldown => {
+                *state = BreakerState::HalfOpen { since: now };
+                Ok(())
+            }
+            BreakerState::HalfOpen { since } => Err(self.cooldown - now.duration_since(since)),
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::Closed { failures: 0 };
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            _ => self.threshold,
+        };
+
+        *state = if failures >= self.threshold {
+            if !matches!(*state, BreakerState::Open { .. }) {
+                tracing::warn!("Circuit opened after {} consecutive failures", failures);
+            }
+            BreakerState::Open {
+                until: Instant::now() + self.cooldown,
+            }
+        } else {
+            BreakerState::Closed { failures }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::StatusCode as Status, routing::get, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn config() -> UpstreamConfig {
+        UpstreamConfig {
+            read_timeout: Duration::from_millis(200),
+            total_timeout: Duration::from_secs(2),
+            retries: 2,
+            backoff_base: Duration::from_millis(1),
+            breaker_threshold: 3,
+            breaker_cooldown: Duration::from_secs(60),
+            ..Default::default()
+        }
+    }
+
+    /// 启动模拟上游：`/flaky` 前两次返回 503，`/missing` 返回 404，
+    /// `/down` 总是 500，`/forbidden` 与 `/unauthorized` 返回 403/401，
+    /// `/throttled` 返回带 `Retry-After: 120` 的 429，`/hang` 不返回
+    async fn fake_upstream() -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/flaky",
+                get(|State(calls): State<Arc<AtomicUsize>>| async move {
+                    match calls.fetch_add(1, Ordering::SeqCst) {
+                        0 | 1 => Err(Status::SERVICE_UNAVAILABLE),
+                        _ => Ok("ok"),
+                    }
+                }),
+            )
+            .route(
+                "/missing",
+                get(|State(calls): State<Arc<AtomicUsize>>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Status::NOT_FOUND
+                }),
+            )
+            .route(
+                "/down",
+                get(|State(calls): State<Arc<AtomicUsize>>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Status::INTERNAL_SERVER_ERROR
+                }),
+            )
+            .route("/forbidden", get(|| async { Status::FORBIDDEN }))
+            .route("/unauthorized", get(|| async { Status::UNAUTHORIZED }))
+            .route(
+                "/throttled",
+                get(|State(calls): State<Arc<AtomicUsize>>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    (Status::TOO_MANY_REQUESTS, [("retry-after", "120")])
+                }),
+            )
+            .route(
+                "/hang",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    "late"
+                }),
+            )
+            .with_state(calls.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), calls)
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let (base, calls) = fake_upstream().await;
+        let upstream = UpstreamClient::new(Client::new(), config());
+
+        let body = upstream.get(&format!("{}/flaky", base)).await.unwrap();
+        assert_eq!(&body[..], b"ok");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        calls.store(0, Ordering::SeqCst);
+        let missing = upstream.get(&format!("{}/missing", base)).await;
+        assert!(matches!(missing, Err(AppError::NotFound(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_status_mapping() {
+        let (base, _) = fake_upstream().await;
+        let upstream = UpstreamClient::new(
+            Client::new(),
+            UpstreamConfig {
+                max_size: 1,
+                ..config()
+            },
+        );
+
+        for path in ["forbidden", "unauthorized"] {
+            let result = upstream.get(&format!("{}/{}", base, path)).await;
+            assert!(matches!(result, Err(AppError::UpstreamUnauthorized(_))));
+        }
+        let result = upstream.get(&format!("{}/flaky", base)).await;
+        assert!(matches!(result, Err(AppError::TooLarge(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_upstream() {
+        let (base, calls) = fake_upstream().await;
+        let upstream = UpstreamClient::new(Client::new(), config());
+
+        // 429 不重试，带上游的 Retry-After，也不会触发熔断
+        for _ in 0..config().breaker_threshold + 1 {
+            let result = upstream.get(&format!("{}/throttled", base)).await;
+            assert!(matches!(
+                result,
+                Err(AppError::UpstreamRateLimited(_, Some(d))) if d == Duration::from_secs(120)
+            ));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        let body = upstream.get(&format!("{}/flaky", base)).await;
+        assert!(!matches!(body, Err(AppError::UpstreamUnavailable(_))));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("30"), Some(Duration::from_secs(30)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let later = httpdate::fmt_http_date(std::time::SystemTime::now() + Duration::from_secs(90));
+        let parsed = parse_retry_after(&later).unwrap();
+        assert!(parsed > Duration::from_secs(80) && parsed <= Duration::from_secs(90));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (base, _) = fake_upstream().await;
+        let upstream = UpstreamClient::new(
+            Client::new(),
+            UpstreamConfig {
+                retries: 0,
+                ..config()
+            },
+        );
+
+        let result = upstream.get(&format!("{}/hang", base)).await;
+        assert!(matches!(result, Err(AppError::UpstreamTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let (base, calls) = fake_upstream().await;
+        let upstream = UpstreamClient::new(Client::new(), config());
+
+        // 三次尝试后熔断
+        let result = upstream.get(&format!("{}/down", base)).await;
+        assert!(matches!(result, Err(AppError::UpstreamError(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // 熔断期间同一主机的请求不再发出
+        let result = upstream.get(&format!("{}/missing", base)).await;
+        assert!(matches!(result, Err(AppError::UpstreamUnavailable(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_breaker_half_open() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure();
+        // 冷却结束后放行试探请求，失败则重新熔断
+        assert!(breaker.allow().is_ok());
+        breaker.record_failure();
+        assert!(matches!(
+            *breaker.state.lock().unwrap(),
+            BreakerState::Open { .. }
+        ));
+        assert!(breaker.allow().is_ok());
+        breaker.record_success();
+        assert!(matches!(
+            *breaker.state.lock().unwrap(),
+            BreakerState::Closed { failures: 0 }
+        ));
+    }
+
+    #[test]
+    fn test_backoff_bounds() {
+        for attempt in 0..5 {
+            let delay = backoff(Duration::from_millis(100), attempt);
+            assert!(delay < Duration::from_millis(100) * (1 << attempt));
+        }
+    }
+}